@@ -0,0 +1,191 @@
+// Borsh impls for proofs, roots and elements: lengths are `u32` little-endian, digests and field
+// reprs are fixed-size arrays with no prefix, so the bytes match what `#[derive(BorshSerialize)]`
+// would produce for the same field layout. Framing errors are borsh's own; a non-canonical field
+// repr or an inconsistent compact proof is an `InvalidData` error carrying the `MerkleError`.
+use crate::error::MerkleError;
+use crate::hasher::Digest;
+use crate::merkle::{CompactProof, Element, Proof, Root};
+use alloc::vec::Vec;
+use borsh::io::{Error, ErrorKind, Read, Result, Write};
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+fn invalid_data(error: MerkleError) -> Error {
+    #[cfg(feature = "std")]
+    return Error::new(ErrorKind::InvalidData, error);
+    #[cfg(not(feature = "std"))]
+    Error::new(
+        ErrorKind::InvalidData,
+        alloc::string::ToString::to_string(&error),
+    )
+}
+
+impl<F: PrimeField + PrimeFieldBits> BorshSerialize for Element<F> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        (self.value.len() as u32).serialize(writer)?;
+        for v in &self.value {
+            writer.write_all(v.to_repr().as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits> BorshDeserialize for Element<F> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        let count = u32::deserialize_reader(reader)? as usize;
+        // The count is untrusted, so the buffer grows as values actually arrive.
+        let mut value = Vec::with_capacity(count.min(64));
+        for _ in 0..count {
+            let mut repr = F::Repr::default();
+            reader.read_exact(repr.as_mut())?;
+            let v = Option::from(F::from_repr(repr))
+                .ok_or_else(|| invalid_data(MerkleError::NonCanonicalField))?;
+            value.push(v);
+        }
+        Ok(Element { value })
+    }
+}
+
+impl<H, const N: usize> BorshSerialize for Proof<H, N> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.sibling_hashes.serialize(writer)?;
+        self.directions.serialize(writer)
+    }
+}
+
+impl<H, const N: usize> BorshDeserialize for Proof<H, N> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok(Proof {
+            sibling_hashes: Vec::<Digest<N>>::deserialize_reader(reader)?,
+            directions: Option::<Vec<bool>>::deserialize_reader(reader)?,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<H, const N: usize> BorshSerialize for CompactProof<H, N> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.bitmap.serialize(writer)?;
+        self.sibling_hashes.serialize(writer)
+    }
+}
+
+impl<H, const N: usize> BorshDeserialize for CompactProof<H, N> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        let bitmap = u32::deserialize_reader(reader)?;
+        let sibling_hashes = Vec::<Digest<N>>::deserialize_reader(reader)?;
+        if sibling_hashes.len() != bitmap.count_ones() as usize {
+            return Err(invalid_data(MerkleError::LengthMismatch {
+                expected: bitmap.count_ones() as usize,
+                got: sibling_hashes.len(),
+            }));
+        }
+        Ok(CompactProof {
+            bitmap,
+            sibling_hashes,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<const N: usize> BorshSerialize for Root<N> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.0.serialize(writer)
+    }
+}
+
+impl<const N: usize> BorshDeserialize for Root<N> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok(Root(Digest::<N>::deserialize_reader(reader)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Sha256Hasher;
+    use crate::merkle::{convert_to_bits, default_hashes, MerkleTree};
+    use pasta_curves::Fp;
+
+    // How a downstream program embeds the crate's types.
+    #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+    struct Claim {
+        index: u64,
+        element: Element<Fp>,
+        proof: Proof,
+        root: Root,
+    }
+
+    #[test]
+    fn borsh_round_trips() {
+        const HEIGHT: usize = 32;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let element = Element::new(vec![Fp::from(7), Fp::from(8)]).unwrap();
+        tree.add_element_at(0, &element).unwrap();
+        tree.add_element_at(1, &element).unwrap();
+        let bits_index = convert_to_bits(HEIGHT, 0).unwrap();
+
+        let bytes = borsh::to_vec(&element).unwrap();
+        assert_eq!(bytes.len(), 4 + 2 * 32);
+        assert_eq!(borsh::from_slice::<Element<Fp>>(&bytes).unwrap(), element);
+
+        let proof = tree.prove(&bits_index).unwrap();
+        let bytes = borsh::to_vec(&proof).unwrap();
+        assert_eq!(bytes.len(), 4 + HEIGHT * 32 + 1);
+        assert_eq!(borsh::from_slice::<Proof>(&bytes).unwrap(), proof);
+        let directed: Proof =
+            Proof::with_directions(proof.sibling_hashes.clone(), bits_index.clone());
+        let bytes = borsh::to_vec(&directed).unwrap();
+        assert_eq!(bytes.len(), 4 + HEIGHT * 32 + 1 + 4 + HEIGHT);
+        assert_eq!(borsh::from_slice::<Proof>(&bytes).unwrap(), directed);
+
+        // Only the leaf's neighbour is not a default, so one digest is kept.
+        let compact = tree.prove_compact(&bits_index).unwrap();
+        let bytes = borsh::to_vec(&compact).unwrap();
+        assert_eq!(bytes.len(), 4 + 4 + 32);
+        let defaults = default_hashes(&Sha256Hasher, &Element::<Fp>::default(), HEIGHT);
+        let decoded = borsh::from_slice::<CompactProof>(&bytes).unwrap();
+        assert!(decoded.validate(bits_index.clone(), &element, tree.top, &defaults));
+
+        let claim = Claim {
+            index: 0,
+            element: element.clone(),
+            proof,
+            root: tree.root(),
+        };
+        let decoded = borsh::from_slice::<Claim>(&borsh::to_vec(&claim).unwrap()).unwrap();
+        assert_eq!(decoded, claim);
+        assert!(decoded
+            .proof
+            .validate(bits_index, &decoded.element, decoded.root.as_bytes()));
+    }
+
+    #[test]
+    fn borsh_rejects_bad_input() {
+        let mut bytes = borsh::to_vec(&Element::new(vec![Fp::from(1)]).unwrap()).unwrap();
+        bytes[4..].fill(0xff);
+        let error = borsh::from_slice::<Element<Fp>>(&bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.into_inner().unwrap().downcast_ref::<MerkleError>(),
+            Some(&MerkleError::NonCanonicalField)
+        );
+
+        let bytes = borsh::to_vec(&Proof::<Sha256Hasher>::new(vec![[1; 32]])).unwrap();
+        assert!(borsh::from_slice::<Proof>(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(borsh::from_slice::<Proof>(&trailing).is_err());
+
+        let mut compact = borsh::to_vec(&CompactProof::<Sha256Hasher> {
+            bitmap: 1,
+            sibling_hashes: vec![[1; 32]],
+            _marker: PhantomData,
+        })
+        .unwrap();
+        compact[0] = 3;
+        let error = borsh::from_slice::<CompactProof>(&compact).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,78 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    MissingNode(Vec<u8>),
+    IndexOutOfRange { index: u64, height: usize },
+    LengthMismatch { expected: usize, got: usize },
+    NonCanonicalField,
+    MalformedSnapshot,
+    LeafOccupied,
+    TreeFull,
+    EmptyElement,
+    UnknownRoot,
+    EmptyValueMismatch,
+    StaleProof,
+    MissingHeight,
+    DirectionMismatch,
+    EmptyProof,
+    MisalignedDigests { width: usize, len: usize },
+    HeightMismatch { height: usize },
+    NodeCapTooSmall { max_nodes: usize, height: usize },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::MissingNode(hash) => {
+                write!(
+                    f,
+                    "node {} is missing from the data store",
+                    hex::encode(hash)
+                )
+            }
+            MerkleError::IndexOutOfRange { index, height } => {
+                write!(f, "index {index} does not fit in a tree of height {height}")
+            }
+            MerkleError::LengthMismatch { expected, got } => {
+                write!(f, "expected {expected} path bits, got {got}")
+            }
+            MerkleError::NonCanonicalField => {
+                write!(f, "bytes are not a canonical field element encoding")
+            }
+            MerkleError::MalformedSnapshot => write!(f, "tree snapshot is truncated or malformed"),
+            MerkleError::LeafOccupied => write!(f, "leaf holds a non-empty element"),
+            MerkleError::TreeFull => write!(f, "every leaf position has been appended"),
+            MerkleError::EmptyElement => write!(f, "element has no field values"),
+            MerkleError::UnknownRoot => write!(f, "root is not in the retained history"),
+            MerkleError::EmptyValueMismatch => write!(f, "trees use different empty values"),
+            MerkleError::StaleProof => write!(f, "proof does not match the current root"),
+            MerkleError::MissingHeight => write!(f, "tree height was not set"),
+            MerkleError::DirectionMismatch => {
+                write!(f, "index does not match the proof's directions")
+            }
+            MerkleError::EmptyProof => write!(f, "proof bytes are empty"),
+            MerkleError::MisalignedDigests { width, len } => {
+                write!(
+                    f,
+                    "{len} bytes is not a whole number of {width}-byte digests"
+                )
+            }
+            MerkleError::HeightMismatch { height } => {
+                write!(
+                    f,
+                    "path reaches a leaf before its last bit in a tree of height {height}"
+                )
+            }
+            MerkleError::NodeCapTooSmall { max_nodes, height } => {
+                write!(
+                    f,
+                    "{max_nodes} stored nodes cannot hold one path of a tree of height {height}"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for MerkleError {}
@@ -0,0 +1,497 @@
+use ff::PrimeField;
+use sha2::{Digest as _, Sha256};
+
+// Every node hash in a tree is a fixed-width array; 32 bytes fits SHA-256 and the reprs of the
+// ~255-bit fields used with Poseidon.
+pub type Digest<const N: usize = 32> = [u8; N];
+
+pub trait Hasher<F: PrimeField, const N: usize = 32>: Clone + Default {
+    fn hash_leaf(&self, values: &[F::Repr]) -> Digest<N>;
+    fn hash_nodes(&self, left: &Digest<N>, right: &Digest<N>) -> Digest<N>;
+
+    // Parent of the children of a `KaryTree` node. The default folds pairwise from the left, so
+    // two children hash exactly as `hash_nodes`.
+    fn hash_children(&self, children: &[Digest<N>]) -> Digest<N> {
+        let (first, rest) = children.split_first().expect("a node has children");
+        rest.iter()
+            .fold(*first, |acc, child| self.hash_nodes(&acc, child))
+    }
+}
+
+// Constant-time in the contents; only the lengths may leak. Every proof check compares its
+// recomputed root through this, while tree-side lookups such as `is_known_root` use plain `==`.
+pub fn digests_match(computed: &[u8], expected: &[u8]) -> bool {
+    subtle::ConstantTimeEq::ct_eq(computed, expected).into()
+}
+
+pub const LEAF_PREFIX: u8 = 0x00;
+pub const NODE_PREFIX: u8 = 0x01;
+pub const SORTED_NODE_PREFIX: u8 = 0x02;
+
+// Hashers whose `hash_nodes` is symmetric, so a proof verifies without direction bits.
+pub trait SortedPairs {}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha256Hasher;
+
+impl<F: PrimeField> Hasher<F> for Sha256Hasher {
+    fn hash_leaf(&self, values: &[F::Repr]) -> Digest {
+        // The value count is framed in so elements of different widths cannot collide. Framing
+        // changed every leaf digest, and with them every root, in 0.2.0 (see CHANGELOG.md).
+        let mut hasher = Sha256::new();
+        hasher.update([LEAF_PREFIX]);
+        hasher.update((values.len() as u32).to_le_bytes());
+        for v in values {
+            hasher.update(v.as_ref());
+        }
+        hasher.finalize().into()
+    }
+
+    fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+        sha256_children(&[*left, *right])
+    }
+
+    fn hash_children(&self, children: &[Digest]) -> Digest {
+        sha256_children(children)
+    }
+}
+
+// `Sha256Hasher` with each pair sorted before hashing, as many Solidity libraries do. Nodes
+// carry their own prefix, so a sorted root never matches a positional one over the same leaves.
+// Position is not committed to: a proof verifies its element at any index.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SortedSha256Hasher;
+
+impl SortedPairs for SortedSha256Hasher {}
+
+impl<F: PrimeField> Hasher<F> for SortedSha256Hasher {
+    fn hash_leaf(&self, values: &[F::Repr]) -> Digest {
+        Hasher::<F>::hash_leaf(&Sha256Hasher, values)
+    }
+
+    fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+        let (low, high) = if left <= right {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        let mut hasher = Sha256::new();
+        hasher.update([SORTED_NODE_PREFIX]);
+        hasher.update(low);
+        hasher.update(high);
+        hasher.finalize().into()
+    }
+}
+
+fn sha256_children(children: &[Digest]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    for child in children {
+        hasher.update(child);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(feature = "poseidon")]
+pub use self::poseidon::PoseidonHasher;
+
+#[cfg(feature = "poseidon")]
+mod poseidon {
+    use super::{Digest, Hasher};
+    use crate::merkle::field_from_repr_bytes;
+    use ff::PrimeField;
+    use generic_array::typenum::U2;
+    use neptune::hash_type::HashType;
+    use neptune::poseidon::{Poseidon, PoseidonConstants};
+    use neptune::Strength;
+    use std::sync::Arc;
+
+    // Digests are the canonical repr bytes of a single field element. Leaves fold their values
+    // through a constant-length instance, internal nodes use the merkle-tree instance, so the two
+    // are separated by Poseidon's domain tag. Bytes that do not decode to a field element, or a
+    // repr that is not `N` bytes wide, hash to all-ones, which is never a canonical repr and so
+    // can never match a real root.
+    #[derive(Clone, Debug)]
+    pub struct PoseidonHasher<F: PrimeField> {
+        leaf_constants: Arc<PoseidonConstants<F, U2>>,
+        node_constants: Arc<PoseidonConstants<F, U2>>,
+    }
+
+    impl<F: PrimeField> Default for PoseidonHasher<F> {
+        fn default() -> Self {
+            Self {
+                leaf_constants: Arc::new(PoseidonConstants::new_with_strength_and_type(
+                    Strength::Standard,
+                    HashType::ConstantLength(2),
+                )),
+                node_constants: Arc::new(PoseidonConstants::new()),
+            }
+        }
+    }
+
+    impl<F: PrimeField> PoseidonHasher<F> {
+        pub fn hash_leaf_field(&self, values: &[F]) -> F {
+            let mut acc = F::from(values.len() as u64);
+            for v in values {
+                acc = Poseidon::new_with_preimage(&[acc, *v], &self.leaf_constants).hash();
+            }
+            acc
+        }
+
+        pub fn hash_nodes_field(&self, left: F, right: F) -> F {
+            Poseidon::new_with_preimage(&[left, right], &self.node_constants).hash()
+        }
+    }
+
+    fn to_digest<F: PrimeField, const N: usize>(value: Option<F>) -> Digest<N> {
+        value
+            .and_then(|v| v.to_repr().as_ref().try_into().ok())
+            .unwrap_or([0xff; N])
+    }
+
+    impl<F: PrimeField, const N: usize> Hasher<F, N> for PoseidonHasher<F> {
+        fn hash_leaf(&self, values: &[F::Repr]) -> Digest<N> {
+            let fields: Option<Vec<F>> = values.iter().map(|r| F::from_repr(*r).into()).collect();
+            to_digest(fields.map(|fields| self.hash_leaf_field(&fields)))
+        }
+
+        fn hash_nodes(&self, left: &Digest<N>, right: &Digest<N>) -> Digest<N> {
+            let fields = field_from_repr_bytes(left).zip(field_from_repr_bytes(right));
+            to_digest(fields.map(|(left, right)| self.hash_nodes_field(left, right)))
+        }
+    }
+}
+
+#[cfg(feature = "keccak")]
+pub use self::keccak::{
+    keccak256, DoubleLeafKeccak256Hasher, Keccak256Hasher, SortedKeccak256Hasher,
+};
+
+#[cfg(feature = "keccak")]
+mod keccak {
+    use super::{Digest, Hasher, SortedPairs};
+    use crate::error::MerkleError;
+    use crate::merkle::{Element, Proof};
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use ff::{PrimeField, PrimeFieldBits};
+    use sha3::{Digest as _, Keccak256};
+
+    pub fn keccak256(data: &[u8]) -> Digest {
+        Keccak256::digest(data).into()
+    }
+
+    // Matches Solidity: a leaf is `keccak256(abi.encode(values))` with each value a `uint256`, and
+    // a node is `keccak256(abi.encodePacked(left, right))`. There is no leaf/node prefix, so roots
+    // line up with contracts that hash pairs directly. Reprs are taken to be little-endian, as
+    // they are for pasta and bls12-381 fields, and are reversed into the big-endian words the ABI
+    // uses.
+    //
+    // Without the prefix a two-value leaf hashes exactly like a node: `[a, b]` gives the parent of
+    // children `a` and `b`, so an internal node and its children verify as a leaf one level up.
+    // Only use this hasher where leaves are known to belong to the tree, or where that
+    // second-preimage is harmless; otherwise use `DoubleLeafKeccak256Hasher`.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Keccak256Hasher;
+
+    impl<F: PrimeField> Hasher<F> for Keccak256Hasher {
+        fn hash_leaf(&self, values: &[F::Repr]) -> Digest {
+            hash_reprs::<F>(values)
+        }
+
+        fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+            hash_pair(left, right)
+        }
+
+        fn hash_children(&self, children: &[Digest]) -> Digest {
+            let mut hasher = Keccak256::new();
+            for child in children {
+                hasher.update(child);
+            }
+            hasher.finalize().into()
+        }
+    }
+
+    // OpenZeppelin's leaf convention, `keccak256(bytes.concat(keccak256(abi.encode(values))))`,
+    // with nodes as in `Keccak256Hasher`. A leaf's preimage is 32 bytes and a node's is 64, so no
+    // node can pass for a leaf.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct DoubleLeafKeccak256Hasher;
+
+    impl<F: PrimeField> Hasher<F> for DoubleLeafKeccak256Hasher {
+        fn hash_leaf(&self, values: &[F::Repr]) -> Digest {
+            keccak256(&hash_reprs::<F>(values))
+        }
+
+        fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+            hash_pair(left, right)
+        }
+    }
+
+    fn hash_reprs<F: PrimeField>(values: &[F::Repr]) -> Digest {
+        let mut hasher = Keccak256::new();
+        let mut word = Vec::new();
+        for v in values {
+            word.clear();
+            word.extend(v.as_ref().iter().rev());
+            hasher.update(&word);
+        }
+        hasher.finalize().into()
+    }
+
+    fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+        let mut hasher = Keccak256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+
+    // OpenZeppelin's `MerkleProof` convention: each pair is sorted before hashing, so
+    // `hash_nodes(a, b) == hash_nodes(b, a)` and a proof carries no left/right information. Leaves
+    // hash exactly like `Keccak256Hasher`, so they share its leaf/node second-preimage caveat.
+    // Since position is not committed to, a proof from such a tree verifies the element at any
+    // index; only build it when that is acceptable, as it is for OpenZeppelin allowlists.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct SortedKeccak256Hasher;
+
+    impl SortedPairs for SortedKeccak256Hasher {}
+
+    impl<F: PrimeField> Hasher<F> for SortedKeccak256Hasher {
+        fn hash_leaf(&self, values: &[F::Repr]) -> Digest {
+            hash_reprs::<F>(values)
+        }
+
+        fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+            if left <= right {
+                hash_pair(left, right)
+            } else {
+                hash_pair(right, left)
+            }
+        }
+    }
+
+    fn to_hex(digest: &Digest) -> String {
+        format!("0x{}", hex::encode(digest))
+    }
+
+    impl Proof<SortedKeccak256Hasher> {
+        // Returns `(leaf, proof, root)` as 0x-prefixed hex, ready for
+        // `MerkleProof.verify(proof, root, leaf)`. OpenZeppelin walks the proof from the leaf up,
+        // so the siblings are emitted bottom-up, the reverse of `sibling_hashes`.
+        pub fn to_solidity<F: PrimeField + PrimeFieldBits>(
+            &self,
+            element: &Element<F>,
+        ) -> Result<(String, Vec<String>, String), MerkleError> {
+            let leaf = element.compute_hash_with(&SortedKeccak256Hasher);
+            let bits_index = alloc::vec![false; self.sibling_hashes.len()];
+            let root = self.calculate_root(bits_index, element)?;
+            let proof = self.sibling_hashes.iter().rev().map(to_hex).collect();
+            Ok((to_hex(&leaf), proof, to_hex(&root)))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::merkle::{convert_to_bits, MerkleTree};
+        use pasta_curves::Fp;
+
+        fn digest(hex: &str) -> Digest {
+            hex::decode(hex).unwrap().try_into().unwrap()
+        }
+
+        #[test]
+        fn keccak256_test_vectors() {
+            assert_eq!(
+                keccak256(b""),
+                digest("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+            );
+            assert_eq!(
+                keccak256(b"abc"),
+                digest("4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45")
+            );
+        }
+
+        #[test]
+        fn roots_match_solidity() {
+            // `keccak256(abi.encode(uint256(0)))`, the empty leaf for a zero value.
+            let empty_leaf =
+                digest("290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563");
+            assert_eq!(
+                Element::<Fp>::default().compute_hash_with(&Keccak256Hasher),
+                empty_leaf
+            );
+            // `keccak256(abi.encode(uint256(1)))`, also the storage slot of an array at slot 1.
+            assert_eq!(
+                Element::single(Fp::from(1)).compute_hash_with(&Keccak256Hasher),
+                digest("b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6")
+            );
+
+            // The zero-subtree roots that Solidity incremental trees hard-code for bytes32(0)
+            // leaves, e.g. `keccak256(abi.encodePacked(bytes32(0), bytes32(0)))`.
+            let zero = [0; 32];
+            let level_1 = Hasher::<Fp>::hash_nodes(&Keccak256Hasher, &zero, &zero);
+            assert_eq!(
+                level_1,
+                digest("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+            );
+            let level_2 = Hasher::<Fp>::hash_nodes(&Keccak256Hasher, &level_1, &level_1);
+            assert_eq!(
+                level_2,
+                digest("b4c11951957c6f8f642c4af61cd6b24640fec6dc7fc607ee8206a99e92410d30")
+            );
+
+            // Roots computed outside this crate by a standalone program on `tiny-keccak` 2.0
+            // (`Keccak::v256`), hashing 32-byte big-endian words. With `e` for `empty_leaf` and
+            // `k` for `keccak256(abi.encodePacked(..))`, the first root is
+            // `k(k(e, k(uint256(1))), k(e, e))` for `[1]` at index 1, and the second is
+            // `k(k(e, k(uint256(1))), k(k(uint256(7), uint256(1000)), e))` after `[7, 1000]` is
+            // written at index 2. `cast keccak` over the same packed bytes gives the same values.
+            let mut tree = MerkleTree::<Fp, Keccak256Hasher>::initialize(Element::default(), 2);
+            tree.add_element_at(1, &Element::single(Fp::from(1)))
+                .unwrap();
+            assert_eq!(
+                tree.top,
+                digest("6f21267e2924835775d03cf48818214cc95760e04b05cfe0320a33f5a5883d59")
+            );
+            let pair = Element::new(vec![Fp::from(7), Fp::from(1000)]).unwrap();
+            tree.add_element_at(2, &pair).unwrap();
+            assert_eq!(
+                tree.top,
+                digest("7667e0356aae7fe92734e267ca204388a1b4a74ebaeaece0f51e81490e4ecd08")
+            );
+        }
+
+        #[test]
+        fn double_leaf_hashing_separates_leaves_from_nodes() {
+            let (a, b) = (Fp::from(7).to_repr(), Fp::from(1000).to_repr());
+            let word = |repr: [u8; 32]| {
+                let mut word = repr;
+                word.reverse();
+                word
+            };
+            // The single-hashed leaf of `[a, b]` is the node over the words `a` and `b`.
+            let node = Hasher::<Fp>::hash_nodes(&Keccak256Hasher, &word(a), &word(b));
+            assert_eq!(Hasher::<Fp>::hash_leaf(&Keccak256Hasher, &[a, b]), node);
+            assert_ne!(
+                Hasher::<Fp>::hash_leaf(&DoubleLeafKeccak256Hasher, &[a, b]),
+                node
+            );
+
+            // Computed by the same standalone program as `roots_match_solidity`, as
+            // `k(k(uint256(0)))` and then `k(k(e, k(k(uint256(1)))), k(k(k(uint256(7),
+            // uint256(1000))), e))` for the tree holding `[1]` at index 1 and `[7, 1000]` at 2.
+            assert_eq!(
+                Element::<Fp>::default().compute_hash_with(&DoubleLeafKeccak256Hasher),
+                digest("510e4e770828ddbf7f7b00ab00a9f6adaf81c0dc9cc85f1f8249c256942d61d9")
+            );
+            let mut tree =
+                MerkleTree::<Fp, DoubleLeafKeccak256Hasher>::initialize(Element::default(), 2);
+            tree.add_element_at(1, &Element::single(Fp::from(1)))
+                .unwrap();
+            let pair = Element::new(vec![Fp::from(7), Fp::from(1000)]).unwrap();
+            tree.add_element_at(2, &pair).unwrap();
+            assert_eq!(
+                tree.top,
+                digest("46c995ccc813bb163abd7e7c15ad55dfd21ee20bc268c9d02f52a7903f5a1b15")
+            );
+            let bits_index = convert_to_bits(2, 2).unwrap();
+            assert!(tree
+                .prove(&bits_index)
+                .unwrap()
+                .validate(bits_index, &pair, tree.top));
+        }
+
+        // A line-for-line port of OpenZeppelin's `MerkleProof.verify` and `_hashPair`.
+        fn oz_verify(proof: &[String], root: &str, leaf: &str) -> bool {
+            let parse = |s: &str| digest(s.strip_prefix("0x").unwrap());
+            let mut computed_hash = parse(leaf);
+            for p in proof {
+                let p = parse(p);
+                computed_hash = if computed_hash < p {
+                    keccak256(&[computed_hash, p].concat())
+                } else {
+                    keccak256(&[p, computed_hash].concat())
+                };
+            }
+            computed_hash == parse(root)
+        }
+
+        #[test]
+        fn solidity_export_verifies_like_openzeppelin() {
+            const HEIGHT: usize = 4;
+            let mut tree =
+                MerkleTree::<Fp, SortedKeccak256Hasher>::initialize(Element::default(), HEIGHT);
+            let elements: Vec<Element<Fp>> = (1..=5)
+                .map(|i| Element {
+                    value: vec![Fp::from(i), Fp::from(i * 100)],
+                })
+                .collect();
+            for (index, element) in elements.iter().enumerate() {
+                tree.add_element_at(index as u64 * 3, element).unwrap();
+            }
+
+            for (index, element) in elements.iter().enumerate() {
+                let bits_index = convert_to_bits(HEIGHT, index as u64 * 3).unwrap();
+                let proof = tree.prove(&bits_index).unwrap();
+                assert!(proof.validate(bits_index, element, tree.top));
+                let (leaf, siblings, root) = proof.to_solidity(element).unwrap();
+                assert_eq!(root, format!("0x{}", hex::encode(tree.top)));
+                assert_eq!(siblings.len(), HEIGHT);
+                assert!(oz_verify(&siblings, &root, &leaf));
+
+                let (other_leaf, _, _) = proof.to_solidity(&Element::<Fp>::default()).unwrap();
+                assert!(!oz_verify(&siblings, &root, &other_leaf));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn leaf_and_node_hashes_are_separated() {
+        let a = Fp::from(3).to_repr();
+        let b = Fp::from(4).to_repr();
+        let hasher = Sha256Hasher;
+        let leaf = Hasher::<Fp>::hash_leaf(&hasher, &[a, b]);
+        let node = Hasher::<Fp>::hash_nodes(&hasher, &a, &b);
+        assert_ne!(leaf, node);
+
+        let mut untagged = Sha256::new();
+        untagged.update(a);
+        untagged.update(b);
+        let untagged: Digest = untagged.finalize().into();
+        assert_ne!(leaf, untagged);
+        assert_ne!(node, untagged);
+    }
+
+    #[test]
+    fn digests_match_compares_whole_digests() {
+        let digest = [7u8; 32];
+        assert!(digests_match(&digest, &digest));
+        let mut last = digest;
+        last[31] ^= 1;
+        assert!(!digests_match(&digest, &last));
+        assert!(!digests_match(&digest, &digest[..31]));
+    }
+
+    #[test]
+    fn leaf_hashes_frame_the_value_count() {
+        let a = Fp::from(3).to_repr();
+        let b = Fp::from(4).to_repr();
+        let mut framed = Sha256::new();
+        framed.update([LEAF_PREFIX]);
+        framed.update(2u32.to_le_bytes());
+        framed.update(a);
+        framed.update(b);
+        let framed: Digest = framed.finalize().into();
+        assert_eq!(Hasher::<Fp>::hash_leaf(&Sha256Hasher, &[a, b]), framed);
+        let unframed: Digest = Sha256::digest([LEAF_PREFIX]).into();
+        assert_ne!(Hasher::<Fp>::hash_leaf(&Sha256Hasher, &[]), unframed);
+    }
+}
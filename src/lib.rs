@@ -1,2 +1,12 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 //pub mod circuit;
+#[cfg(feature = "borsh")]
+mod borsh_impl;
+pub mod error;
+pub mod hasher;
 pub mod merkle;
+#[cfg(feature = "serde")]
+mod serde_impl;
@@ -1,10 +1,71 @@
-extern crate sha2;
+use crate::error::MerkleError;
+use crate::hasher::{digests_match, Digest, Hasher, Sha256Hasher, SortedPairs};
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use ff::{PrimeField, PrimeFieldBits};
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::marker::PhantomData;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[cfg(all(test, feature = "bls"))]
+mod bls;
+mod builder;
+mod cached;
+mod dense;
+#[cfg(feature = "test-utils")]
+mod fixtures;
+mod forest;
+mod frozen;
+mod kary;
+mod keyed;
+mod mmr;
+mod multiproof;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod shared;
+mod snapshot;
+#[cfg(feature = "async")]
+mod stream;
+mod typed;
+mod verifier;
+#[cfg(feature = "viz")]
+mod viz;
+
+pub use self::builder::MerkleTreeBuilder;
+pub use self::cached::CachedElement;
+pub use self::dense::DenseTree;
+#[cfg(feature = "test-utils")]
+pub use self::fixtures::sample_tree;
+pub use self::forest::{ForestProof, ForestTree};
+pub use self::frozen::FrozenTree;
+pub use self::kary::{convert_to_digits, KaryProof, KaryTree};
+pub use self::keyed::key_to_bits;
+pub use self::mmr::{Mmr, MmrProof};
+pub use self::multiproof::MultiProof;
+pub use self::shared::TreeSnapshot;
+#[cfg(feature = "async")]
+pub use self::stream::Stream;
+pub use self::typed::TypedProof;
+pub use self::verifier::CachingVerifier;
+
+// Internal nodes are keyed by hash; `no_std` builds fall back to ordered collections from `alloc`.
+#[cfg(feature = "std")]
+pub type NodeMap<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub type NodeMap<K, V> = BTreeMap<K, V>;
+#[cfg(feature = "std")]
+type NodeSet<K> = std::collections::HashSet<K>;
+#[cfg(not(feature = "std"))]
+type NodeSet<K> = alloc::collections::BTreeSet<K>;
+
+// A stored element with its proof, as `get_and_prove` returns it.
+pub type ProvenElement<F, H = Sha256Hasher, const N: usize = 32> = (Element<F>, Proof<H, N>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Element<F: PrimeField + PrimeFieldBits> {
     pub value: Vec<F>,
 }
@@ -17,149 +78,4026 @@ impl<F: PrimeField + PrimeFieldBits> Default for Element<F> {
     }
 }
 
+// A value that hashes to a leaf digest on its own terms, for data that is not naturally a vector
+// of field elements. `MerkleTree::add_leaf` and `Mmr::append_leaf` write one, keeping only its
+// digest, and both proof types check one with `validate_leaf`.
+pub trait Leaf<F: PrimeField> {
+    fn hash_with<H: Hasher<F, N>, const N: usize>(&self, hasher: &H) -> Digest<N>;
+}
+
+impl<F: PrimeField + PrimeFieldBits> Leaf<F> for Element<F> {
+    fn hash_with<H: Hasher<F, N>, const N: usize>(&self, hasher: &H) -> Digest<N> {
+        self.compute_hash_with(hasher)
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits> From<F> for Element<F> {
+    fn from(value: F) -> Self {
+        Self::single(value)
+    }
+}
+
+// Unlike `new`, an empty vector converts; the tree still refuses to store the result.
+impl<F: PrimeField + PrimeFieldBits> From<Vec<F>> for Element<F> {
+    fn from(value: Vec<F>) -> Self {
+        Self { value }
+    }
+}
+
 impl<F: PrimeField + PrimeFieldBits> Element<F> {
-    pub fn compute_hash(&self) -> Vec<u8> {
-        let mut hasher = Sha256::new();
+    pub fn new(value: Vec<F>) -> Result<Self, MerkleError> {
+        if value.is_empty() {
+            return Err(MerkleError::EmptyElement);
+        }
+        Ok(Self { value })
+    }
+
+    pub fn single(value: F) -> Self {
+        Self { value: vec![value] }
+    }
+
+    // For values supplied as canonical reprs by outside code. A repr at or above the modulus, or
+    // of the wrong width, is refused instead of reduced, so each leaf has one byte encoding.
+    pub fn from_reprs<B: AsRef<[u8]>>(reprs: &[B]) -> Result<Self, MerkleError> {
+        let value = reprs
+            .iter()
+            .map(|repr| field_from_repr_bytes(repr.as_ref()).ok_or(MerkleError::NonCanonicalField))
+            .collect::<Result<Vec<F>, _>>()?;
+        Self::new(value)
+    }
+
+    // An empty `value` still hashes (to the hasher's digest of no reprs), so proofs about one can
+    // be checked, but the tree refuses to store it: the empty leaf is `vec![F::ZERO]`, and an
+    // empty vector is almost always a caller bug.
+    pub fn compute_hash(&self) -> Digest {
+        self.compute_hash_with(&Sha256Hasher)
+    }
+
+    pub fn compute_hash_with<H: Hasher<F, N>, const N: usize>(&self, hasher: &H) -> Digest<N> {
+        self.compute_hash_in(hasher, &mut Vec::new())
+    }
+
+    // Leaf hash under a caller-chosen domain: the element's hash paired with the hash of
+    // `Element::from_bytes(domain)`, so equal values in different domains hash apart.
+    pub fn compute_hash_with_domain(&self, domain: &[u8]) -> Digest {
+        self.compute_domain_hash_with(&Sha256Hasher, domain)
+    }
+
+    pub fn compute_domain_hash_with<H: Hasher<F, N>, const N: usize>(
+        &self,
+        hasher: &H,
+        domain: &[u8],
+    ) -> Digest<N> {
+        domain_hash(hasher, domain, &self.compute_hash_with(hasher))
+    }
+
+    // Like `compute_hash_with`, but encodes the values into `scratch` so a caller hashing many
+    // leaves can reuse one buffer.
+    pub fn compute_hash_in<H: Hasher<F, N>, const N: usize>(
+        &self,
+        hasher: &H,
+        scratch: &mut Vec<F::Repr>,
+    ) -> Digest<N> {
+        Self::hash_values_in(&self.value, hasher, scratch)
+    }
+
+    // The hash an element holding `values` would have, for borrowed values that would otherwise
+    // be cloned into an `Element` just to be hashed.
+    pub fn hash_values(values: &[F]) -> Digest {
+        Self::hash_values_in(values, &Sha256Hasher, &mut Vec::new())
+    }
+
+    pub fn hash_values_in<H: Hasher<F, N>, const N: usize>(
+        values: &[F],
+        hasher: &H,
+        scratch: &mut Vec<F::Repr>,
+    ) -> Digest<N> {
+        scratch.clear();
+        scratch.extend(values.iter().map(|v| v.to_repr()));
+        hasher.hash_leaf(scratch)
+    }
+
+    // Bytes per value: the most whole bytes below the modulus, so every chunk maps to a distinct
+    // field element without reduction.
+    fn chunk_width() -> usize {
+        F::CAPACITY as usize / 8
+    }
+
+    // Splits `bytes` into little-endian chunks of `chunk_width()` bytes. A 0x01 byte is appended
+    // and the last chunk is zero-filled, so inputs that differ only in trailing zeros stay
+    // distinct and `to_bytes` can strip the padding exactly.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut padded = bytes.to_vec();
+        padded.push(0x01);
+        let width = Self::chunk_width();
+        padded.resize(padded.len().div_ceil(width) * width, 0);
+        let value = padded
+            .chunks(width)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .rev()
+                    .fold(F::ZERO, |acc, &b| acc * F::from(256) + F::from(b as u64))
+            })
+            .collect();
+        Self { value }
+    }
+
+    // Inverse of `from_bytes`. Bits of a value above `chunk_width()` bytes are not kept, and an
+    // element without the padding marker is returned unstripped.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let width = Self::chunk_width();
+        let mut bytes = Vec::with_capacity(self.value.len() * width);
         for v in &self.value {
-            hasher.update(v.to_repr().as_ref());
+            let bits = v.to_le_bits();
+            bytes.extend(
+                (0..width).map(|i| (0..8).fold(0u8, |b, j| b | (bits[8 * i + j] as u8) << j)),
+            );
         }
-        hasher.finalize().to_vec()
+        if let Some(marker) = bytes.iter().rposition(|&b| b != 0) {
+            if bytes[marker] == 0x01 {
+                bytes.truncate(marker);
+            }
+        }
+        bytes
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct MerkleTree<F: PrimeField + PrimeFieldBits> {
-    pub top: Vec<u8>,
-    pub data_store: HashMap<Vec<u8>, (Vec<u8>, Vec<u8>)>,
+pub struct MerkleTree<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    pub top: Digest<N>,
+    pub data_store: NodeMap<Digest<N>, (Digest<N>, Digest<N>)>,
     pub _marker: PhantomData<F>,
+    height: usize,
+    empty_value: Element<F>,
+    default_hashes: Vec<Digest<N>>,
+    leaves: BTreeMap<Vec<bool>, Element<F>>,
+    // Positions last written by `add_leaf`, which keeps only the leaf hash.
+    leaf_digests: BTreeMap<Vec<bool>, Digest<N>>,
+    live_nodes: usize,
+    next_index: u64,
+    hasher: H,
+    root_history: VecDeque<Digest<N>>,
+    undo_history: VecDeque<Undo<F, N>>,
+    history_capacity: usize,
+    domain: Option<Vec<u8>>,
+    max_nodes: Option<usize>,
+    access: AccessLog<N>,
+    #[cfg(feature = "metrics")]
+    hash_count: HashCounter,
+}
+
+// The last path walk through each stored node, for `evict` to drop the least recently used. Walks
+// take `&self`, parallel proving included, so they only bump the atomics that writes insert. Only
+// kept with `max_nodes` set; a node without an entry counts as just used.
+#[derive(Debug, Default)]
+struct AccessLog<const N: usize> {
+    generation: AtomicUsize,
+    last_used: NodeMap<Digest<N>, AtomicUsize>,
+}
+
+impl<const N: usize> AccessLog<N> {
+    fn next_generation(&self) -> usize {
+        self.generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn touch(&self, node_hash: &Digest<N>, generation: usize) {
+        if let Some(last_used) = self.last_used.get(node_hash) {
+            last_used.fetch_max(generation, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<const N: usize> Clone for AccessLog<N> {
+    fn clone(&self) -> Self {
+        let load = |atomic: &AtomicUsize| AtomicUsize::new(atomic.load(Ordering::Relaxed));
+        Self {
+            generation: load(&self.generation),
+            last_used: self
+                .last_used
+                .iter()
+                .map(|(hash, last_used)| (*hash, load(last_used)))
+                .collect(),
+        }
+    }
+}
+
+// Node hashes computed by the latest counted operation. Atomic so that `&self` paths, including
+// the parallel batch, can count; clones start from the current value.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct HashCounter(core::sync::atomic::AtomicUsize);
+
+#[cfg(feature = "metrics")]
+impl Clone for HashCounter {
+    fn clone(&self) -> Self {
+        Self(self.0.load(core::sync::atomic::Ordering::Relaxed).into())
+    }
+}
+
+// Trees are equal when they commit to the same root; `deep_eq` also compares what is stored.
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> PartialEq
+    for MerkleTree<F, H, N>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.top == other.top
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> Eq for MerkleTree<F, H, N> {}
+
+// What `rollback_to` needs to step from one entry of the root history back to the one before it:
+// the append cursor and the previous value of every leaf written since, in write order.
+#[derive(Clone, Debug)]
+struct Undo<F: PrimeField + PrimeFieldBits, const N: usize> {
+    next_index: u64,
+    leaves: Vec<(Vec<bool>, StoredLeaf<F, N>)>,
+}
+
+// What a position held before a write: an element, a bare leaf hash from `add_leaf`, or nothing.
+#[derive(Clone, Debug)]
+enum StoredLeaf<F: PrimeField + PrimeFieldBits, const N: usize> {
+    Element(Element<F>),
+    Digest(Digest<N>),
+    Empty,
 }
 
-impl<F: PrimeField + PrimeFieldBits> MerkleTree<F> {
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
     pub fn initialize(empty_value: Element<F>, height: usize) -> Self {
-        let mut data_store = HashMap::<Vec<u8>, (Vec<u8>, Vec<u8>)>::new();
-        let mut current_hash = empty_value.compute_hash();
-        for _ in 0..height {
-            let pair = (current_hash.clone(), current_hash.clone());
-            current_hash = Self::combine_hashes(&current_hash, &current_hash);
-            data_store.insert(current_hash.clone(), pair);
+        Self::initialize_with_hasher(empty_value, height, H::default())
+    }
+
+    pub fn initialize_with_hasher(empty_value: Element<F>, height: usize, hasher: H) -> Self {
+        let default_hashes = default_hashes(&hasher, &empty_value, height);
+        Self::from_defaults(empty_value, default_hashes, hasher)
+    }
+
+    // `defaults[level]` stands for an all-empty subtree of that height, so the interior padding
+    // need not be the hash of its children. Level 0 must still be the hash of `empty_value`, the
+    // leaf written by `remove_element`.
+    pub fn initialize_with_defaults(
+        empty_value: Element<F>,
+        height: usize,
+        defaults: Vec<Digest<N>>,
+    ) -> Result<Self, MerkleError> {
+        if defaults.len() != height + 1 {
+            return Err(MerkleError::LengthMismatch {
+                expected: height + 1,
+                got: defaults.len(),
+            });
+        }
+        let hasher = H::default();
+        if defaults[0] != empty_value.compute_hash_with(&hasher) {
+            return Err(MerkleError::EmptyValueMismatch);
         }
+        Ok(Self::from_defaults(empty_value, defaults, hasher))
+    }
+
+    // Every leaf, the empty one included, is hashed with `compute_domain_hash_with`, so a proof
+    // from a tree in another domain (or none) fails against this tree's root.
+    pub fn initialize_with_domain(empty_value: Element<F>, height: usize, domain: &[u8]) -> Self {
+        Self::initialize_in_domain(empty_value, height, domain, H::default())
+    }
+
+    fn initialize_in_domain(
+        empty_value: Element<F>,
+        height: usize,
+        domain: &[u8],
+        hasher: H,
+    ) -> Self {
+        let empty_hash = empty_value.compute_domain_hash_with(&hasher, domain);
+        let defaults = default_hashes_from(&hasher, empty_hash, height);
+        let mut tree = Self::from_defaults(empty_value, defaults, hasher);
+        tree.domain = Some(domain.to_vec());
+        tree
+    }
+
+    pub fn domain(&self) -> Option<&[u8]> {
+        self.domain.as_deref()
+    }
+
+    fn from_defaults(empty_value: Element<F>, default_hashes: Vec<Digest<N>>, hasher: H) -> Self {
+        // Empty subtrees are not stored: a missing node that matches its level's default resolves
+        // to two default children, so a fresh tree holds no nodes at any height.
+        let height = default_hashes.len() - 1;
         Self {
-            top: current_hash,
-            data_store,
+            top: default_hashes[height],
+            data_store: NodeMap::new(),
             _marker: PhantomData,
+            height,
+            empty_value,
+            default_hashes,
+            leaves: BTreeMap::new(),
+            leaf_digests: BTreeMap::new(),
+            live_nodes: 0,
+            next_index: 0,
+            hasher,
+            root_history: VecDeque::new(),
+            undo_history: VecDeque::new(),
+            history_capacity: 0,
+            domain: None,
+            max_nodes: None,
+            access: AccessLog::default(),
+            #[cfg(feature = "metrics")]
+            hash_count: HashCounter::default(),
         }
     }
 
-    pub fn add_element(&mut self, mut bits_index: Vec<bool>, element: &Element<F>) {
-        let mut path = self.get_sibling_hashes(&bits_index);
-        bits_index.reverse();
-        let mut current_hash = element.compute_hash();
-        for direction in bits_index {
-            let sibling = path.pop().unwrap();
-            let (left, right) = if direction {
-                (sibling, current_hash.clone())
-            } else {
-                (current_hash.clone(), sibling)
-            };
-            current_hash = Self::combine_hashes(&left, &right);
-            self.data_store.insert(current_hash.clone(), (left, right));
+    // Keeps the latest `capacity` distinct roots, oldest first, starting with the empty root.
+    pub fn initialize_with_history(
+        empty_value: Element<F>,
+        height: usize,
+        capacity: usize,
+    ) -> Self {
+        let mut tree = Self::initialize(empty_value, height);
+        tree.history_capacity = capacity;
+        tree.record_root();
+        tree
+    }
+
+    pub fn recent_roots(&self) -> &VecDeque<Digest<N>> {
+        &self.root_history
+    }
+
+    pub fn is_known_root(&self, root: impl AsRef<[u8]>) -> bool {
+        let root = root.as_ref();
+        root == self.top || self.root_history.iter().any(|known| known == root)
+    }
+
+    fn record_root(&mut self) {
+        if self.history_capacity == 0 || self.root_history.back() == Some(&self.top) {
+            return;
+        }
+        if self.root_history.len() == self.history_capacity {
+            self.root_history.pop_front();
+            self.undo_history.pop_front();
         }
-        self.top = current_hash;
+        self.root_history.push_back(self.top);
+        self.undo_history.push_back(Undo {
+            next_index: self.next_index,
+            leaves: Vec::new(),
+        });
     }
 
-    fn combine_hashes(left: &[u8], right: &[u8]) -> Vec<u8> {
-        let mut hasher = Sha256::new();
-        hasher.update(left);
-        hasher.update(right);
-        hasher.finalize().to_vec()
-    }
-
-    pub fn get_sibling_hashes(&self, bits_index: &[bool]) -> Vec<Vec<u8>> {
-        let mut node_hash = self.top.clone();
-        let mut siblings = Vec::<Vec<u8>>::new();
-        for &direction in bits_index {
-            let (left, right) = self.data_store.get(&node_hash).unwrap();
-            if direction {
-                node_hash = right.clone();
-                siblings.push(left.clone());
-            } else {
-                node_hash = left.clone();
-                siblings.push(right.clone());
+    // Restores the leaves, append cursor and root as they were when `root` was the newest root in
+    // the history, dropping every later entry. Nodes of later states stay in the store until the
+    // next prune.
+    pub fn rollback_to(&mut self, root: impl AsRef<[u8]>) -> Result<(), MerkleError> {
+        let root = root.as_ref();
+        let position = self
+            .root_history
+            .iter()
+            .rposition(|known| known == root)
+            .ok_or(MerkleError::UnknownRoot)?;
+        while self.root_history.len() > position + 1 {
+            self.root_history.pop_back();
+            let undo = self.undo_history.pop_back().unwrap();
+            for (bits_index, previous) in undo.leaves.into_iter().rev() {
+                self.restore_leaf(bits_index, previous);
             }
+            self.next_index = undo.next_index;
         }
-        siblings
+        self.top = *self.root_history.back().unwrap();
+        Ok(())
     }
-}
 
-pub fn convert_to_bits(depth: usize, index: u64) -> Vec<bool> {
-    let mut bits: Vec<bool> = (0..depth).map(|i| ((index >> i) & 1) == 1).collect();
-    bits.reverse();
-    bits
-}
+    pub fn default_hash_at_level(&self, level: usize) -> &Digest<N> {
+        &self.default_hashes[level]
+    }
 
-pub struct Proof {
-    pub sibling_hashes: Vec<Vec<u8>>,
-}
+    // The value `remove_element` writes and every unwritten position holds, as passed in when the
+    // tree was built. Its leaf hash is `default_hash_at_level(0)`.
+    pub fn empty_element(&self) -> &Element<F> {
+        &self.empty_value
+    }
 
-impl Proof {
-    pub fn calculate_root<F: PrimeField + PrimeFieldBits>(
+    // Appends `elements` at indices 0, 1, 2, ... in one batch.
+    pub fn from_elements(
+        empty_value: Element<F>,
+        height: usize,
+        elements: impl IntoIterator<Item = Element<F>>,
+    ) -> Result<Self, MerkleError> {
+        let mut tree = Self::initialize(empty_value, height);
+        let mut entries = Vec::new();
+        for (index, element) in (0u64..).zip(elements) {
+            let bits_index = convert_to_bits(height, index).map_err(|_| MerkleError::TreeFull)?;
+            entries.push((bits_index, element));
+        }
+        tree.next_index = entries.len() as u64;
+        tree.add_batch(entries)?;
+        Ok(tree)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // The digest this tree stores for `element`, under the tree's own hasher. `compute_hash` is
+    // always SHA-256 and only agrees for the default hasher.
+    pub fn leaf_hash(&self, element: &Element<F>) -> Digest<N> {
+        self.hash_leaf(element)
+    }
+
+    fn hash_leaf<L: Leaf<F>>(&self, leaf: &L) -> Digest<N> {
+        self.in_domain(leaf.hash_with(&self.hasher))
+    }
+
+    // Wraps a plain leaf hash in the tree's domain, if it has one.
+    fn in_domain(&self, hash: Digest<N>) -> Digest<N> {
+        match &self.domain {
+            Some(domain) => domain_hash(&self.hasher, domain, &hash),
+            None => hash,
+        }
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        TreeStats {
+            occupied: self.len(),
+            nodes_stored: self.data_store.len(),
+            height: self.height,
+            est_proof_bytes: proof_size_bytes(self.height, N),
+        }
+    }
+
+    // Number of leaf positions, or `None` from height 64 up where it no longer fits in a `u64`.
+    pub fn capacity(&self) -> Option<u64> {
+        u32::try_from(self.height)
+            .ok()
+            .and_then(|height| 1u64.checked_shl(height))
+    }
+
+    pub fn add_element(
+        &mut self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+    ) -> Result<(), MerkleError> {
+        self.insert(bits_index, element).map(|_| ())
+    }
+
+    pub fn add_element_unchecked(&mut self, bits_index: Vec<bool>, element: &Element<F>) {
+        self.add_element(bits_index, element).unwrap()
+    }
+
+    // Writes any `Leaf` by its hash alone, so the position counts as occupied and proves like an
+    // element's, but `get_element` and `iter` have no value to return for it. Snapshots, rebuilds
+    // and merges carry the hash along.
+    pub fn add_leaf<L: Leaf<F>>(
+        &mut self,
+        bits_index: Vec<bool>,
+        leaf: &L,
+    ) -> Result<(), MerkleError> {
+        self.start_op();
+        let leaf_hash = self.hash_leaf(leaf);
+        self.write_leaf(bits_index.clone(), leaf_hash)?;
+        self.store_leaf_hash(bits_index, leaf_hash);
+        Ok(())
+    }
+
+    fn store_leaf_hash(&mut self, bits_index: Vec<bool>, leaf_hash: Digest<N>) {
+        let stored = if leaf_hash == self.default_hashes[0] {
+            StoredLeaf::Empty
+        } else {
+            StoredLeaf::Digest(leaf_hash)
+        };
+        self.replace_leaf(bits_index, stored);
+    }
+
+    // `add_element` with map semantics: returns the element that was replaced, `None` if the
+    // position was empty.
+    pub fn insert(
+        &mut self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+    ) -> Result<Option<Element<F>>, MerkleError> {
+        self.start_op();
+        check_element(element)?;
+        let leaf_hash = self.leaf_hash(element);
+        self.write_leaf(bits_index.clone(), leaf_hash)?;
+        Ok(self.store_leaf(bits_index, element.clone(), &leaf_hash))
+    }
+
+    // Leaves that hash like the empty value are unoccupied, so they are dropped from the value
+    // store instead of being kept as explicit entries.
+    fn store_leaf(
+        &mut self,
+        bits_index: Vec<bool>,
+        element: Element<F>,
+        leaf_hash: &Digest<N>,
+    ) -> Option<Element<F>> {
+        let element = (*leaf_hash != self.default_hashes[0]).then_some(element);
+        self.set_leaf(bits_index, element)
+    }
+
+    fn set_leaf(
+        &mut self,
+        bits_index: Vec<bool>,
+        element: Option<Element<F>>,
+    ) -> Option<Element<F>> {
+        let stored = element.map_or(StoredLeaf::Empty, StoredLeaf::Element);
+        match self.replace_leaf(bits_index, stored) {
+            StoredLeaf::Element(previous) => Some(previous),
+            _ => None,
+        }
+    }
+
+    // Puts `stored` at `bits_index`, recording what it replaces for `rollback_to`.
+    fn replace_leaf(
+        &mut self,
+        bits_index: Vec<bool>,
+        stored: StoredLeaf<F, N>,
+    ) -> StoredLeaf<F, N> {
+        let previous = self.restore_leaf(bits_index.clone(), stored);
+        if let Some(undo) = self.undo_history.back_mut() {
+            undo.leaves.push((bits_index, previous.clone()));
+        }
+        previous
+    }
+
+    fn restore_leaf(
+        &mut self,
+        bits_index: Vec<bool>,
+        stored: StoredLeaf<F, N>,
+    ) -> StoredLeaf<F, N> {
+        let digest = self.leaf_digests.remove(&bits_index);
+        let element = self.leaves.remove(&bits_index);
+        match stored {
+            StoredLeaf::Element(element) => {
+                self.leaves.insert(bits_index, element);
+            }
+            StoredLeaf::Digest(digest) => {
+                self.leaf_digests.insert(bits_index, digest);
+            }
+            StoredLeaf::Empty => {}
+        }
+        match (element, digest) {
+            (Some(element), _) => StoredLeaf::Element(element),
+            (None, Some(digest)) => StoredLeaf::Digest(digest),
+            (None, None) => StoredLeaf::Empty,
+        }
+    }
+
+    // The leaf hash currently at `bits_index`, occupied or not.
+    fn stored_leaf_hash(&self, bits_index: &[bool]) -> Digest<N> {
+        if let Some(element) = self.leaves.get(bits_index) {
+            return self.leaf_hash(element);
+        }
+        self.leaf_digests
+            .get(bits_index)
+            .copied()
+            .unwrap_or(self.default_hashes[0])
+    }
+
+    // The root `insert` would produce, computed from the current siblings without writing.
+    pub fn predict_root(
         &self,
-        mut bits_index: Vec<bool>,
+        bits_index: &[bool],
         element: &Element<F>,
-    ) -> Vec<u8> {
-        bits_index.reverse();
-        let mut current_hash = element.compute_hash();
-        for (i, sibling) in self.sibling_hashes.iter().rev().enumerate() {
-            let (left, right) = if bits_index[i] {
-                (sibling, &current_hash)
+    ) -> Result<Digest<N>, MerkleError> {
+        self.check_index(bits_index)?;
+        check_element(element)?;
+        let sibling_hashes = self.get_sibling_hashes(bits_index)?;
+        let levels = bits_index.iter().zip(&sibling_hashes).enumerate().rev();
+        Ok(levels.fold(
+            self.leaf_hash(element),
+            |hash, (depth, (&direction, sibling))| {
+                if direction {
+                    self.node_at(depth, sibling, &hash)
+                } else {
+                    self.node_at(depth, &hash, sibling)
+                }
+            },
+        ))
+    }
+
+    pub fn add_element_at(&mut self, index: u64, element: &Element<F>) -> Result<(), MerkleError> {
+        self.add_element(convert_to_bits(self.height, index)?, element)
+    }
+
+    // Writes at the next unused append position and returns it. Appends and random-access writes
+    // share the tree, so an explicit write ahead of the cursor is overwritten when it is reached.
+    pub fn append(&mut self, element: &Element<F>) -> Result<u64, MerkleError> {
+        let index = self.next_index;
+        let full = index == u64::MAX || self.capacity().is_some_and(|capacity| index >= capacity);
+        if full {
+            return Err(MerkleError::TreeFull);
+        }
+        self.add_element_at(index, element)?;
+        self.next_index += 1;
+        Ok(index)
+    }
+
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    pub fn remove_element(&mut self, bits_index: Vec<bool>) -> Result<(), MerkleError> {
+        self.start_op();
+        let leaf_hash = self.default_hashes[0];
+        self.write_leaf(bits_index.clone(), leaf_hash)?;
+        self.set_leaf(bits_index, None);
+        Ok(())
+    }
+
+    fn write_leaf(
+        &mut self,
+        bits_index: Vec<bool>,
+        leaf_hash: Digest<N>,
+    ) -> Result<(), MerkleError> {
+        self.check_index(&bits_index)?;
+        let path = self.get_sibling_hashes(&bits_index)?;
+        self.write_path(&bits_index, &path, leaf_hash);
+        Ok(())
+    }
+
+    // Writes the new path and moves `top`; `path` must be the current siblings of `bits_index`.
+    fn write_path(&mut self, bits_index: &[bool], path: &[Digest<N>], leaf_hash: Digest<N>) {
+        let mut current_hash = leaf_hash;
+        for (depth, (&direction, &sibling)) in bits_index.iter().zip(path).enumerate().rev() {
+            let (left, right) = if direction {
+                (sibling, current_hash)
             } else {
-                (&current_hash, sibling)
+                (current_hash, sibling)
             };
-            current_hash = MerkleTree::<F>::combine_hashes(left, right);
+            current_hash = self.node_at(depth, &left, &right);
+            if !self.is_default(&current_hash, depth) {
+                self.store_node(current_hash, (left, right));
+            }
         }
-        current_hash
+        self.set_top(current_hash);
     }
 
-    pub fn validate<F: PrimeField + PrimeFieldBits>(
-        &self,
+    // Like `add_element`, but takes the siblings from a proof for `bits_index` instead of walking
+    // the store. The proof is trusted only once it folds the current leaf up to `top`.
+    pub fn update_with_proof(
+        &mut self,
         bits_index: Vec<bool>,
         element: &Element<F>,
-        root_hash: &[u8],
-    ) -> bool {
-        self.calculate_root(bits_index, element) == root_hash
+        proof: &Proof<H, N>,
+    ) -> Result<(), MerkleError> {
+        self.start_op();
+        check_element(element)?;
+        self.check_index(&bits_index)?;
+        if proof.expected_height() != self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: proof.expected_height(),
+            });
+        }
+        let old_leaf = self.stored_leaf_hash(&bits_index);
+        let levels = bits_index.iter().zip(&proof.sibling_hashes).enumerate();
+        let old_root =
+            levels
+                .rev()
+                .fold(old_leaf, |current_hash, (depth, (&direction, sibling))| {
+                    if direction {
+                        self.node_at(depth, sibling, &current_hash)
+                    } else {
+                        self.node_at(depth, &current_hash, sibling)
+                    }
+                });
+        if old_root != self.top {
+            return Err(MerkleError::StaleProof);
+        }
+        let leaf_hash = self.leaf_hash(element);
+        self.write_path(&bits_index, &proof.sibling_hashes, leaf_hash);
+        self.store_leaf(bits_index, element.clone(), &leaf_hash);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use ff::Field;
-    use pasta_curves::Fp;
+    // New nodes count as used by the write that made them.
+    fn store_node(&mut self, node_hash: Digest<N>, children: (Digest<N>, Digest<N>)) {
+        if self.max_nodes.is_some() {
+            let generation = self.access.generation.load(Ordering::Relaxed);
+            self.access
+                .last_used
+                .insert(node_hash, AtomicUsize::new(generation));
+        }
+        self.data_store.insert(node_hash, children);
+    }
 
-    #[test]
-    fn check_leaves() {
-        const HEIGHT: usize = 32;
-        let empty_element = Element::<Fp>::default();
-        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+    fn store_nodes(&mut self, nodes: NodeMap<Digest<N>, (Digest<N>, Digest<N>)>) {
+        for (node_hash, children) in nodes {
+            self.store_node(node_hash, children);
+        }
+    }
 
-        for i in 0..50 {
-            let index = i;
-            let bits_index = convert_to_bits(HEIGHT, index);
-            let element = Element {
-                value: vec![Fp::random(&mut rand::thread_rng())],
-            };
+    fn set_top(&mut self, top: Digest<N>) {
+        self.top = top;
+        self.record_root();
+        if self.data_store.len() > 2 * self.live_nodes + self.height {
+            self.prune();
+        }
+        if self
+            .max_nodes
+            .is_some_and(|max| self.data_store.len() > max)
+        {
+            self.evict();
+        }
+    }
 
-            let path_siblings = tree.get_sibling_hashes(&bits_index);
-            let proof = Proof {
-                sibling_hashes: path_siblings,
-            };
-            assert!(!proof.validate(bits_index.clone(), &element, &tree.top));
-            tree.add_element(bits_index.clone(), &element);
-            let new_path_siblings = tree.get_sibling_hashes(&bits_index);
-            let new_proof = Proof {
-                sibling_hashes: new_path_siblings,
+    // Keeps the half of `max_nodes` most recently walked, so the next eviction is many writes
+    // away. `top` is always kept, since every walk starts there.
+    fn evict(&mut self) {
+        let keep = self.max_nodes.map_or(0, |max| max / 2).max(1);
+        let now = self.access.generation.load(Ordering::Relaxed);
+        let mut by_recency: Vec<(usize, Digest<N>)> = self
+            .data_store
+            .keys()
+            .map(|hash| {
+                let last_used = match self.access.last_used.get(hash) {
+                    _ if *hash == self.top => usize::MAX,
+                    Some(last_used) => last_used.load(Ordering::Relaxed),
+                    None => now,
+                };
+                (last_used, *hash)
+            })
+            .collect();
+        if by_recency.len() > keep {
+            by_recency.select_nth_unstable_by(keep, |a, b| b.0.cmp(&a.0));
+            by_recency.truncate(keep);
+        }
+        let kept: NodeSet<Digest<N>> = by_recency.iter().map(|(_, hash)| *hash).collect();
+        self.data_store.retain(|hash, _| kept.contains(hash));
+        self.access.last_used = by_recency
+            .into_iter()
+            .map(|(last_used, hash)| (hash, AtomicUsize::new(last_used.min(now))))
+            .collect();
+        self.live_nodes = self.data_store.len();
+    }
+
+    // Drops every internal node that is no longer reachable from `top`. Writes call this on their
+    // own once the store has grown past twice its last live size, so the amortized cost per write
+    // stays constant.
+    pub fn prune(&mut self) {
+        let mut live = NodeSet::new();
+        let mut pending = vec![self.top];
+        pending.extend(&self.root_history);
+        while let Some(hash) = pending.pop() {
+            if let Some((left, right)) = self.data_store.get(&hash) {
+                if live.insert(hash) {
+                    pending.push(*left);
+                    pending.push(*right);
+                }
+            }
+        }
+        self.data_store.retain(|hash, _| live.contains(hash));
+        self.live_nodes = self.data_store.len();
+    }
+
+    pub fn add_batch(&mut self, entries: Vec<(Vec<bool>, Element<F>)>) -> Result<(), MerkleError> {
+        self.start_op();
+        let sorted = self.sort_batch(entries)?;
+        if sorted.is_empty() {
+            return Ok(());
+        }
+        let hashed: Vec<(Vec<bool>, Digest<N>)> = sorted
+            .iter()
+            .map(|(bits_index, element)| (bits_index.clone(), self.leaf_hash(element)))
+            .collect();
+        let mut created = NodeMap::new();
+        let top = self.update_subtree(self.top, 0, self.height, &hashed, &mut created)?;
+        self.store_nodes(created);
+        self.set_top(top);
+        for ((bits_index, element), (_, leaf_hash)) in sorted.into_iter().zip(&hashed) {
+            self.store_leaf(bits_index, element, leaf_hash);
+        }
+        Ok(())
+    }
+
+    fn sort_batch(
+        &self,
+        entries: Vec<(Vec<bool>, Element<F>)>,
+    ) -> Result<BTreeMap<Vec<bool>, Element<F>>, MerkleError> {
+        let mut sorted = BTreeMap::new();
+        for (bits_index, element) in entries {
+            self.check_index(&bits_index)?;
+            check_element(&element)?;
+            sorted.insert(bits_index, element);
+        }
+        Ok(sorted)
+    }
+
+    // Rewrites the subtree under `node_hash` at `depth` with the given sorted, deduplicated
+    // hashes for the nodes at `leaf_depth`, recomputing each ancestor once and leaving untouched
+    // siblings as they are. Only nodes of the current tree are read, so new nodes are collected
+    // in `created` rather than written to the store.
+    fn update_subtree(
+        &self,
+        node_hash: Digest<N>,
+        depth: usize,
+        leaf_depth: usize,
+        entries: &[(Vec<bool>, Digest<N>)],
+        created: &mut NodeMap<Digest<N>, (Digest<N>, Digest<N>)>,
+    ) -> Result<Digest<N>, MerkleError> {
+        if depth == leaf_depth {
+            return Ok(entries[0].1);
+        }
+        let (mut left, mut right) = self.children_at(&node_hash, &entries[0].0[..depth])?;
+        let split = entries.partition_point(|(bits_index, _)| !bits_index[depth]);
+        let (lower, upper) = entries.split_at(split);
+        if !lower.is_empty() {
+            left = self.update_subtree(left, depth + 1, leaf_depth, lower, created)?;
+        }
+        if !upper.is_empty() {
+            right = self.update_subtree(right, depth + 1, leaf_depth, upper, created)?;
+        }
+        let hash = self.node_at(depth, &left, &right);
+        if !self.is_default(&hash, depth) {
+            created.insert(hash, (left, right));
+        }
+        Ok(hash)
+    }
+
+    fn check_index(&self, bits_index: &[bool]) -> Result<(), MerkleError> {
+        if bits_index.len() != self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: bits_index.len(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn get_element(&self, bits_index: &[bool]) -> Option<Element<F>> {
+        self.leaves.get(bits_index).cloned()
+    }
+
+    // Counts positions written by `add_leaf` as well as elements.
+    pub fn contains(&self, bits_index: &[bool]) -> bool {
+        self.leaves.contains_key(bits_index) || self.leaf_digests.contains_key(bits_index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len() + self.leaf_digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty() && self.leaf_digests.is_empty()
+    }
+
+    // Occupied leaves holding elements in ascending index order. Positions past `u64::MAX`, which
+    // only raw-bit writes to trees taller than 64 can reach, are left out; `iter_bits` covers
+    // those too.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &Element<F>)> {
+        self.iter_bits()
+            .filter_map(|(bits_index, element)| Some((convert_from_bits(bits_index)?, element)))
+    }
+
+    pub fn iter_bits(&self) -> impl Iterator<Item = (&[bool], &Element<F>)> {
+        self.leaves
+            .iter()
+            .map(|(bits_index, element)| (bits_index.as_slice(), element))
+    }
+
+    pub fn get_sibling_hashes(&self, bits_index: &[bool]) -> Result<Vec<Digest<N>>, MerkleError> {
+        self.start_op();
+        if self.max_nodes.is_none() {
+            return sibling_hashes(&self.data_store, &self.default_hashes, self.top, bits_index);
+        }
+        let generation = self.access.next_generation();
+        let mut node_hash = self.top;
+        let mut siblings = Vec::with_capacity(bits_index.len());
+        for (depth, &direction) in bits_index.iter().enumerate() {
+            let (left, right) = self.children_at(&node_hash, &bits_index[..depth])?;
+            self.access.touch(&node_hash, generation);
+            node_hash = if direction { right } else { left };
+            siblings.push(if direction { left } else { right });
+        }
+        Ok(siblings)
+    }
+
+    // The node at `depth` over `left` and `right`. Two empty children give that level's default
+    // rather than their hash, which differ when `initialize_with_defaults` set custom padding.
+    fn node_at(&self, depth: usize, left: &Digest<N>, right: &Digest<N>) -> Digest<N> {
+        let empty = &self.default_hashes[self.height - depth - 1];
+        if left == empty && right == empty {
+            return self.default_hashes[self.height - depth];
+        }
+        self.hash_nodes(left, right)
+    }
+
+    fn hash_nodes(&self, left: &Digest<N>, right: &Digest<N>) -> Digest<N> {
+        #[cfg(feature = "metrics")]
+        self.hash_count
+            .0
+            .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        self.hasher.hash_nodes(left, right)
+    }
+
+    // Marks the start of an operation reported by `last_op_hash_count`.
+    fn start_op(&self) {
+        #[cfg(feature = "metrics")]
+        self.hash_count
+            .0
+            .store(0, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    // Node hashes computed by the latest write, batch or sibling lookup.
+    #[cfg(feature = "metrics")]
+    pub fn last_op_hash_count(&self) -> usize {
+        self.hash_count
+            .0
+            .load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Empty subtrees resolve through `node_children`, so they are never written to the store.
+    fn is_default(&self, node_hash: &Digest<N>, depth: usize) -> bool {
+        *node_hash == self.default_hashes[self.height - depth]
+    }
+
+    // `prefix` is the path from the top to `node_hash`. With `max_nodes` set, a node that was
+    // evicted is rebuilt from the leaves below it, and only trusted if it rehashes to `node_hash`.
+    fn children_at(
+        &self,
+        node_hash: &Digest<N>,
+        prefix: &[bool],
+    ) -> Result<(Digest<N>, Digest<N>), MerkleError> {
+        let stored = node_children(
+            &self.data_store,
+            &self.default_hashes,
+            node_hash,
+            prefix.len(),
+        );
+        if self.max_nodes.is_none() || !matches!(stored, Err(MerkleError::MissingNode(_))) {
+            return stored;
+        }
+        let mut child = prefix.to_vec();
+        child.push(false);
+        let left = self.hash_from_leaves(&child);
+        *child.last_mut().unwrap() = true;
+        let right = self.hash_from_leaves(&child);
+        if self.node_at(prefix.len(), &left, &right) != *node_hash {
+            return stored;
+        }
+        Ok((left, right))
+    }
+
+    fn hash_from_leaves(&self, prefix: &[bool]) -> Digest<N> {
+        let leaves = self.leaf_hashes(prefix);
+        self.fold_leaves(prefix.len(), &leaves, &mut None)
+    }
+
+    // Hashes of the occupied leaves under `prefix`, elements and bare hashes alike, in index
+    // order.
+    fn leaf_hashes(&self, prefix: &[bool]) -> Vec<(&[bool], Digest<N>)> {
+        let elements = self
+            .leaves
+            .range(prefix.to_vec()..)
+            .take_while(|(bits_index, _)| bits_index.starts_with(prefix))
+            .map(|(bits_index, element)| (bits_index.as_slice(), self.leaf_hash(element)));
+        let digests = self
+            .leaf_digests
+            .range(prefix.to_vec()..)
+            .take_while(|(bits_index, _)| bits_index.starts_with(prefix))
+            .map(|(bits_index, digest)| (bits_index.as_slice(), *digest));
+        let mut leaves: Vec<_> = elements.chain(digests).collect();
+        leaves.sort_unstable_by_key(|(bits_index, _)| *bits_index);
+        leaves
+    }
+
+    // Hash of the subtree at `depth` holding the sorted `leaves`, adding each non-default node to
+    // `created` when given.
+    fn fold_leaves(
+        &self,
+        depth: usize,
+        leaves: &[(&[bool], Digest<N>)],
+        created: &mut Option<NodeMap<Digest<N>, (Digest<N>, Digest<N>)>>,
+    ) -> Digest<N> {
+        if leaves.is_empty() {
+            return self.default_hashes[self.height - depth];
+        }
+        if depth == self.height {
+            return leaves[0].1;
+        }
+        let split = leaves.partition_point(|(bits_index, _)| !bits_index[depth]);
+        let left = self.fold_leaves(depth + 1, &leaves[..split], created);
+        let right = self.fold_leaves(depth + 1, &leaves[split..], created);
+        let hash = self.node_at(depth, &left, &right);
+        if let Some(created) = created {
+            if !self.is_default(&hash, depth) {
+                created.insert(hash, (left, right));
+            }
+        }
+        hash
+    }
+
+    // Every node of the current tree, rebuilt from the leaves when some may have been evicted, so
+    // snapshots stay complete.
+    fn all_nodes(&self) -> Cow<'_, NodeMap<Digest<N>, (Digest<N>, Digest<N>)>> {
+        if self.max_nodes.is_none() {
+            return Cow::Borrowed(&self.data_store);
+        }
+        Cow::Owned(self.nodes_from_leaves().1)
+    }
+
+    fn nodes_from_leaves(&self) -> (Digest<N>, NodeMap<Digest<N>, (Digest<N>, Digest<N>)>) {
+        let leaves = self.leaf_hashes(&[]);
+        let mut created = Some(NodeMap::new());
+        let top = self.fold_leaves(0, &leaves, &mut created);
+        (top, created.unwrap_or_default())
+    }
+
+    // Replaces every internal node with one recomputed from the stored leaves and returns the
+    // resulting root. A root that differs from the previous `top` means the store had been
+    // corrupted; either way the tree is consistent with its elements afterwards.
+    pub fn rebuild_from_leaves(&mut self) -> Digest<N> {
+        self.start_op();
+        let (top, nodes) = self.nodes_from_leaves();
+        self.data_store.clear();
+        self.store_nodes(nodes);
+        self.live_nodes = self.data_store.len();
+        self.set_top(top);
+        top
+    }
+
+    // The bound `evict` keeps `data_store` under, if any.
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.max_nodes
+    }
+
+    // The two children of an internal node, for walking the tree without relying on how
+    // `data_store` is laid out. `None` for leaves and for hashes this tree does not hold.
+    pub fn children(&self, node_hash: &[u8]) -> Option<(&Digest<N>, &Digest<N>)> {
+        let node_hash: &Digest<N> = node_hash.try_into().ok()?;
+        if let Some((left, right)) = self.data_store.get(node_hash) {
+            return Some((left, right));
+        }
+        let level = (1..=self.height).find(|&level| self.default_hashes[level] == *node_hash)?;
+        let child = &self.default_hashes[level - 1];
+        Some((child, child))
+    }
+
+    // Hash of the node reached by following `prefix_bits` from the top, committing to every leaf
+    // below it. Unwritten subtrees give their level default; an empty prefix gives `top`.
+    pub fn subtree_root(&self, prefix_bits: &[bool]) -> Result<Digest<N>, MerkleError> {
+        if prefix_bits.len() > self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: prefix_bits.len(),
+            });
+        }
+        let mut node_hash = self.top;
+        for (depth, &direction) in prefix_bits.iter().enumerate() {
+            let (left, right) = self.children_at(&node_hash, &prefix_bits[..depth])?;
+            node_hash = if direction { right } else { left };
+        }
+        Ok(node_hash)
+    }
+
+    // Hashes of the nodes on the path below the root, from the root's child down to the leaf.
+    pub fn get_path_hashes(&self, bits_index: &[bool]) -> Result<Vec<Digest<N>>, MerkleError> {
+        let generation = self.access.next_generation();
+        let mut node_hash = self.top;
+        let mut path = Vec::<Digest<N>>::new();
+        for (depth, &direction) in bits_index.iter().enumerate() {
+            let (left, right) = self.children_at(&node_hash, &bits_index[..depth])?;
+            self.access.touch(&node_hash, generation);
+            node_hash = if direction { right } else { left };
+            path.push(node_hash);
+        }
+        Ok(path)
+    }
+
+    // The `_unchecked` variants panic on the errors their checked counterparts return, for callers
+    // that have already validated the index and element.
+    pub fn get_sibling_hashes_unchecked(&self, bits_index: &[bool]) -> Vec<Digest<N>> {
+        self.get_sibling_hashes(bits_index).unwrap()
+    }
+
+    pub fn prove(&self, bits_index: &[bool]) -> Result<Proof<H, N>, MerkleError> {
+        self.check_index(bits_index)?;
+        Ok(Proof::new(self.get_sibling_hashes(bits_index)?))
+    }
+
+    pub fn prove_at(&self, index: u64) -> Result<Proof<H, N>, MerkleError> {
+        self.prove(&convert_to_bits(self.height, index)?)
+    }
+
+    // An authenticated read: the stored element with its proof against `top`, or `None` for an
+    // empty position.
+    pub fn get_and_prove(&self, index: u64) -> Result<Option<ProvenElement<F, H, N>>, MerkleError> {
+        let bits_index = convert_to_bits(self.height, index)?;
+        let Some(element) = self.get_element(&bits_index) else {
+            return Ok(None);
+        };
+        Ok(Some((element, self.prove(&bits_index)?)))
+    }
+
+    pub fn prove_with_root(
+        &self,
+        bits_index: &[bool],
+    ) -> Result<(Proof<H, N>, Digest<N>), MerkleError> {
+        Ok((self.prove(bits_index)?, self.top))
+    }
+
+    // Proof of `index` only up to the root of its enclosing subtree of height `window_height`,
+    // returned alongside it. The proof validates with the last `window_height` bits of the index
+    // against that sub-root, which `prove_subtree_root` ties back to `top`.
+    pub fn prove_windowed(
+        &self,
+        index: u64,
+        window_height: usize,
+    ) -> Result<(Proof<H, N>, Digest<N>), MerkleError> {
+        if window_height > self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: window_height,
+            });
+        }
+        let bits_index = convert_to_bits(self.height, index)?;
+        let prefix = &bits_index[..self.height - window_height];
+        let sibling_hashes = self.get_sibling_hashes(&bits_index)?;
+        let proof = Proof::new(sibling_hashes[prefix.len()..].to_vec());
+        Ok((proof, self.subtree_root(prefix)?))
+    }
+
+    // Siblings from the top down to the node at `prefix_bits`, so `validate_hash` with that
+    // prefix and `subtree_root(prefix_bits)` checks the sub-root against `top`.
+    pub fn prove_subtree_root(&self, prefix_bits: &[bool]) -> Result<Proof<H, N>, MerkleError> {
+        if prefix_bits.len() > self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: prefix_bits.len(),
+            });
+        }
+        Ok(Proof::new(self.get_sibling_hashes(prefix_bits)?))
+    }
+
+    pub fn prove_compact(&self, bits_index: &[bool]) -> Result<CompactProof<H, N>, MerkleError> {
+        self.prove(bits_index)?.compress(&self.default_hashes)
+    }
+
+    // Indices whose leaves differ between the two trees, ascending. Subtrees with equal hashes are
+    // skipped whole, so the cost follows the number of differences rather than the tree size.
+    pub fn diff(&self, other: &Self) -> Result<Vec<u64>, MerkleError> {
+        if self.height != other.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: other.height,
+            });
+        }
+        if self.default_hashes[0] != other.default_hashes[0] {
+            return Err(MerkleError::EmptyValueMismatch);
+        }
+        let mut changed = Vec::new();
+        let mut prefix = Vec::with_capacity(self.height);
+        self.diff_subtree(other, self.top, other.top, &mut prefix, &mut changed)?;
+        Ok(changed)
+    }
+
+    fn diff_subtree(
+        &self,
+        other: &Self,
+        mine: Digest<N>,
+        theirs: Digest<N>,
+        prefix: &mut Vec<bool>,
+        changed: &mut Vec<u64>,
+    ) -> Result<(), MerkleError> {
+        if mine == theirs {
+            return Ok(());
+        }
+        if prefix.len() == self.height {
+            let index = convert_from_bits(prefix).ok_or(MerkleError::IndexOutOfRange {
+                index: u64::MAX,
+                height: self.height,
+            })?;
+            changed.push(index);
+            return Ok(());
+        }
+        let (my_left, my_right) = self.children_at(&mine, prefix)?;
+        let (their_left, their_right) = other.children_at(&theirs, prefix)?;
+        for (direction, mine, theirs) in
+            [(false, my_left, their_left), (true, my_right, their_right)]
+        {
+            prefix.push(direction);
+            self.diff_subtree(other, mine, theirs, prefix, changed)?;
+            prefix.pop();
+        }
+        Ok(())
+    }
+
+    // Moves the occupied leaves of `other` into `self` as one batch, so shared ancestors are
+    // rehashed once. Both trees must agree on height and empty leaf hash, and no position may be
+    // occupied in both; on error `self` is unchanged. The append cursor becomes the later of the
+    // two.
+    pub fn merge(&mut self, other: Self) -> Result<(), MerkleError> {
+        if self.height != other.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: other.height,
+            });
+        }
+        if self.default_hashes[0] != other.default_hashes[0] {
+            return Err(MerkleError::EmptyValueMismatch);
+        }
+        let theirs = other.leaves.keys().chain(other.leaf_digests.keys());
+        if theirs.clone().any(|bits_index| self.contains(bits_index)) {
+            return Err(MerkleError::LeafOccupied);
+        }
+        self.add_batch(other.leaves.into_iter().collect())?;
+        let digests: Vec<_> = other.leaf_digests.into_iter().collect();
+        if !digests.is_empty() {
+            let mut created = NodeMap::new();
+            let top = self.update_subtree(self.top, 0, self.height, &digests, &mut created)?;
+            self.store_nodes(created);
+            self.set_top(top);
+            for (bits_index, leaf_hash) in digests {
+                self.store_leaf_hash(bits_index, leaf_hash);
+            }
+        }
+        self.next_index = self.next_index.max(other.next_index);
+        Ok(())
+    }
+
+    // Same root, height, empty value and occupied leaves. Unpruned nodes and root history are
+    // bookkeeping and are not compared.
+    pub fn deep_eq(&self, other: &Self) -> bool {
+        self.top == other.top
+            && self.height == other.height
+            && self.empty_value == other.empty_value
+            && self.default_hashes == other.default_hashes
+            && self.leaves == other.leaves
+            && self.leaf_digests == other.leaf_digests
+    }
+
+    // SHA-256 over the height and the occupied (position, element) pairs in index order, so trees
+    // holding the same data agree whatever hasher built their roots. Leaves from `add_leaf`
+    // follow as (position, hash) pairs; only those depend on the hasher.
+    pub fn content_fingerprint(&self) -> [u8; 32] {
+        use sha2::{Digest as _, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.update((self.height as u64).to_le_bytes());
+        for (bits_index, element) in &self.leaves {
+            hasher.update(snapshot::pack_bits(bits_index));
+            hasher.update((element.value.len() as u32).to_le_bytes());
+            for v in &element.value {
+                hasher.update(v.to_repr());
+            }
+        }
+        for (bits_index, digest) in &self.leaf_digests {
+            hasher.update(snapshot::pack_bits(bits_index));
+            hasher.update(digest);
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn root(&self) -> Root<N> {
+        Root(self.top)
+    }
+
+    pub fn prove_non_membership(&self, bits_index: &[bool]) -> Result<Proof<H, N>, MerkleError> {
+        if self.contains(bits_index) {
+            return Err(MerkleError::LeafOccupied);
+        }
+        self.prove(bits_index)
+    }
+}
+
+// A tree root, kept apart from other byte strings. `Display` and `{:x}` print lowercase hex,
+// `{:#x}` with a `0x` prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Root<const N: usize = 32>(pub Digest<N>);
+
+impl<const N: usize> Root<N> {
+    pub fn as_bytes(&self) -> &Digest<N> {
+        &self.0
+    }
+}
+
+impl<const N: usize> From<Digest<N>> for Root<N> {
+    fn from(digest: Digest<N>) -> Self {
+        Self(digest)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Root<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> fmt::LowerHex for Root<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        for byte in &self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for Root<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:x}")
+    }
+}
+
+// Root first: entry `i` is the sibling at depth `i + 1`, paired with `bits_index[i]`, so the leaf's
+// own sibling is last. Rebuilding a path walks both in reverse.
+fn sibling_hashes<const N: usize>(
+    data_store: &NodeMap<Digest<N>, (Digest<N>, Digest<N>)>,
+    default_hashes: &[Digest<N>],
+    top: Digest<N>,
+    bits_index: &[bool],
+) -> Result<Vec<Digest<N>>, MerkleError> {
+    let mut node_hash = top;
+    let mut siblings = Vec::<Digest<N>>::new();
+    for (depth, &direction) in bits_index.iter().enumerate() {
+        let (left, right) = node_children(data_store, default_hashes, &node_hash, depth)?;
+        if direction {
+            node_hash = right;
+            siblings.push(left);
+        } else {
+            node_hash = left;
+            siblings.push(right);
+        }
+    }
+    Ok(siblings)
+}
+
+// A leaf hash paired with the hash of `Element::from_bytes(domain)`, as domain trees store it.
+fn domain_hash<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    domain: &[u8],
+    leaf_hash: &Digest<N>,
+) -> Digest<N> {
+    let tag = Element::<F>::from_bytes(domain).compute_hash_with(hasher);
+    hasher.hash_nodes(&tag, leaf_hash)
+}
+
+// Children of the node at `depth`, falling back to the level defaults for an empty subtree that
+// was never stored. A node at the tree height is a leaf, so a path with bits left over is the
+// caller's mistake rather than a gap in the store.
+fn node_children<const N: usize>(
+    data_store: &NodeMap<Digest<N>, (Digest<N>, Digest<N>)>,
+    default_hashes: &[Digest<N>],
+    node_hash: &Digest<N>,
+    depth: usize,
+) -> Result<(Digest<N>, Digest<N>), MerkleError> {
+    let height = default_hashes.len() - 1;
+    if depth >= height {
+        return Err(MerkleError::HeightMismatch { height });
+    }
+    if let Some(children) = data_store.get(node_hash) {
+        return Ok(*children);
+    }
+    let level = height - depth;
+    if level > 0 && *node_hash == default_hashes[level] {
+        let child = default_hashes[level - 1];
+        return Ok((child, child));
+    }
+    Err(MerkleError::MissingNode(node_hash.to_vec()))
+}
+
+// Entry `level` is the root of an empty subtree of that height, so index 0 is the empty leaf hash
+// and index `height` is the empty tree root.
+pub fn default_hashes<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    empty_value: &Element<F>,
+    height: usize,
+) -> Vec<Digest<N>> {
+    default_hashes_from(hasher, empty_value.compute_hash_with(hasher), height)
+}
+
+fn default_hashes_from<F: PrimeField, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    empty_hash: Digest<N>,
+    height: usize,
+) -> Vec<Digest<N>> {
+    let mut current_hash = empty_hash;
+    let mut default_hashes = Vec::with_capacity(height + 1);
+    for _ in 0..height {
+        let next = hasher.hash_nodes(&current_hash, &current_hash);
+        default_hashes.push(current_hash);
+        current_hash = next;
+    }
+    default_hashes.push(current_hash);
+    default_hashes
+}
+
+// Size of a proof in the flat framing of `Proof::to_flat_bytes`: one digest per level.
+pub fn proof_size_bytes(height: usize, digest_width: usize) -> usize {
+    height * digest_width
+}
+
+// Capacity-planning figures from `MerkleTree::stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeStats {
+    pub occupied: usize,
+    pub nodes_stored: usize,
+    pub height: usize,
+    pub est_proof_bytes: usize,
+}
+
+// Root of a tree of `height` holding only `empty`, for light clients checking non-membership
+// without a tree of their own.
+pub fn empty_root<F: PrimeField + PrimeFieldBits>(empty: &Element<F>, height: usize) -> Digest {
+    empty_root_with(&Sha256Hasher, empty, height)
+}
+
+pub fn empty_root_with<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    empty: &Element<F>,
+    height: usize,
+) -> Digest<N> {
+    (0..height).fold(empty.compute_hash_with(hasher), |hash, _| {
+        hasher.hash_nodes(&hash, &hash)
+    })
+}
+
+fn check_element<F: PrimeField + PrimeFieldBits>(element: &Element<F>) -> Result<(), MerkleError> {
+    if element.value.is_empty() {
+        return Err(MerkleError::EmptyElement);
+    }
+    Ok(())
+}
+
+pub fn field_from_repr_bytes<F: PrimeField>(bytes: &[u8]) -> Option<F> {
+    let mut repr = F::Repr::default();
+    if repr.as_ref().len() != bytes.len() {
+        return None;
+    }
+    repr.as_mut().copy_from_slice(bytes);
+    F::from_repr(repr).into()
+}
+
+// Most significant bit first: `bits[0]` picks the root's child, as every tree path expects.
+pub fn convert_to_bits(depth: usize, index: u64) -> Result<Vec<bool>, MerkleError> {
+    if depth < u64::BITS as usize && index >> depth != 0 {
+        return Err(MerkleError::IndexOutOfRange {
+            index,
+            height: depth,
+        });
+    }
+    let mut bits: Vec<bool> = (0..depth)
+        .map(|i| i < u64::BITS as usize && ((index >> i) & 1) == 1)
+        .collect();
+    bits.reverse();
+    Ok(bits)
+}
+
+// Inverse of `convert_to_bits`; `None` if the value does not fit in a `u64`.
+pub fn convert_from_bits(bits_index: &[bool]) -> Option<u64> {
+    let skip = bits_index.len().saturating_sub(u64::BITS as usize);
+    if bits_index[..skip].contains(&true) {
+        return None;
+    }
+    Some(
+        bits_index[skip..]
+            .iter()
+            .fold(0, |index, &bit| (index << 1) | bit as u64),
+    )
+}
+
+// Least significant bit first, the order of `F::to_le_bits` and so of an in-circuit boolean
+// decomposition of the index as a field element: `bits[0]` picks the leaf's side in its parent.
+// This is `convert_to_bits` reversed; reverse it again before using it as a tree path.
+pub fn index_to_field_bits<F: PrimeFieldBits>(
+    index: u64,
+    height: usize,
+) -> Result<Vec<bool>, MerkleError> {
+    convert_to_bits(height, index)?;
+    let bits = F::from(index).to_le_bits();
+    Ok((0..height)
+        .map(|i| bits.get(i).is_some_and(|bit| *bit))
+        .collect())
+}
+
+// `sibling_hashes` is ordered as `get_sibling_hashes` returns it: the root's child first, the
+// leaf's sibling last. `directions`, when present, is the `bits_index` the proof was made for,
+// and any index checked against the proof must equal it.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Proof<H = Sha256Hasher, const N: usize = 32> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::digests"))]
+    pub sibling_hashes: Vec<Digest<N>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub directions: Option<Vec<bool>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _marker: PhantomData<H>,
+}
+
+// Written out so that `H` need not implement the traits itself.
+impl<H, const N: usize> PartialEq for Proof<H, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.sibling_hashes == other.sibling_hashes && self.directions == other.directions
+    }
+}
+
+impl<H, const N: usize> Eq for Proof<H, N> {}
+
+impl<H, const N: usize> core::hash::Hash for Proof<H, N> {
+    fn hash<S: core::hash::Hasher>(&self, state: &mut S) {
+        self.sibling_hashes.hash(state);
+        self.directions.hash(state);
+    }
+}
+
+impl<H, const N: usize> Proof<H, N> {
+    pub fn new(sibling_hashes: Vec<Digest<N>>) -> Self {
+        Self {
+            sibling_hashes,
+            directions: None,
+            _marker: PhantomData,
+        }
+    }
+
+    // For siblings decoded from untrusted input. Digests are fixed-width arrays from here on, so
+    // this is the one place a truncated or padded sibling can be caught; it is refused rather than
+    // hashed into a bogus root.
+    pub fn from_sibling_slices<B: AsRef<[u8]>>(siblings: &[B]) -> Result<Self, MerkleError> {
+        let sibling_hashes = siblings
+            .iter()
+            .map(|sibling| {
+                let sibling = sibling.as_ref();
+                sibling.try_into().map_err(|_| MerkleError::LengthMismatch {
+                    expected: N,
+                    got: sibling.len(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(sibling_hashes))
+    }
+
+    pub fn with_directions(sibling_hashes: Vec<Digest<N>>, directions: Vec<bool>) -> Self {
+        Self {
+            sibling_hashes,
+            directions: Some(directions),
+            _marker: PhantomData,
+        }
+    }
+
+    fn matches_directions(&self, bits_index: &[bool]) -> bool {
+        self.directions
+            .as_deref()
+            .is_none_or(|directions| directions == bits_index)
+    }
+
+    // Validates at the position recorded in the proof; false for a proof without directions.
+    pub fn validate_directed<F: PrimeField + PrimeFieldBits>(
+        &self,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        match &self.directions {
+            Some(directions) => self.validate(directions.clone(), element, root_hash),
+            None => false,
+        }
+    }
+
+    // Height of the tree this proof claims to come from. A root alone does not fix the height, so
+    // a verifier that knows its tree should compare the two: a truncated proof with an interior
+    // node passed off as the leaf otherwise folds to the same root.
+    pub fn expected_height(&self) -> usize {
+        self.sibling_hashes.len()
+    }
+
+    // `(level, sibling)` in the order `calculate_root` hashes them, where `level` is the height of
+    // the sibling's subtree: the leaf's sibling at 0 first, the root's child last.
+    pub fn siblings_with_levels(&self) -> impl Iterator<Item = (usize, &Digest<N>)> {
+        self.sibling_hashes.iter().rev().enumerate()
+    }
+
+    pub fn calculate_root<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root_in(&bits_index, element, &mut Vec::new())
+    }
+
+    pub fn calculate_root_unchecked<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+    ) -> Digest<N>
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root(bits_index, element).unwrap()
+    }
+
+    fn calculate_root_in<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        element: &Element<F>,
+        scratch: &mut Vec<F::Repr>,
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        let leaf_hash = element.compute_hash_in(&H::default(), scratch);
+        self.root_from_leaf_hash::<F>(bits_index, leaf_hash)
+    }
+
+    // `calculate_root`, calling `on_hash` once per node hash.
+    #[cfg(feature = "metrics")]
+    pub fn calculate_root_with_hook<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        element: &Element<F>,
+        on_hash: &mut dyn FnMut(),
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        let leaf_hash = element.compute_hash_with(&H::default());
+        self.fold_path::<F>(bits_index, leaf_hash, on_hash)
+    }
+
+    fn root_from_leaf_hash<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        leaf_hash: Digest<N>,
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        self.fold_path::<F>(bits_index, leaf_hash, || {})
+    }
+
+    fn fold_path<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        leaf_hash: Digest<N>,
+        mut on_hash: impl FnMut(),
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        let hasher = H::default();
+        self.calculate_root_with(bits_index, leaf_hash, |left, right| {
+            on_hash();
+            hasher.hash_nodes(left, right)
+        })
+    }
+
+    // Folds the path with `combine(left, right)` in place of the proof's hasher, for leaves and
+    // nodes hashed by a scheme this crate does not implement. The length and direction checks
+    // still apply.
+    pub fn calculate_root_with(
+        &self,
+        bits_index: &[bool],
+        leaf_hash: Digest<N>,
+        mut combine: impl FnMut(&Digest<N>, &Digest<N>) -> Digest<N>,
+    ) -> Result<Digest<N>, MerkleError> {
+        if bits_index.len() != self.expected_height() {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.expected_height(),
+                got: bits_index.len(),
+            });
+        }
+        if !self.matches_directions(bits_index) {
+            return Err(MerkleError::DirectionMismatch);
+        }
+        let levels = bits_index.iter().zip(&self.sibling_hashes).rev();
+        Ok(
+            levels.fold(leaf_hash, |current_hash, (&direction, sibling)| {
+                if direction {
+                    combine(sibling, &current_hash)
+                } else {
+                    combine(&current_hash, sibling)
+                }
+            }),
+        )
+    }
+
+    // For verifiers that only hold the leaf's hash, e.g. when the prover reveals a commitment but
+    // not the value. `F` only selects the hasher, so it usually needs to be named.
+    pub fn validate_hash<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        leaf_hash: &[u8],
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let Ok(leaf_hash) = leaf_hash.try_into() else {
+            return false;
+        };
+        self.root_from_leaf_hash::<F>(bits_index, leaf_hash)
+            .is_ok_and(|root| digests_match(&root, root_hash.as_ref()))
+    }
+
+    // For proofs from a tree built with `initialize_with_domain`.
+    pub fn validate_in_domain<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        element: &Element<F>,
+        domain: &[u8],
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let leaf_hash = element.compute_domain_hash_with(&H::default(), domain);
+        self.validate_hash::<F>(bits_index, &leaf_hash, root_hash)
+    }
+
+    // Allocation-free once `scratch` has grown to the element width: for verifiers checking many
+    // proofs, reuse one buffer across calls.
+    pub fn verify_in<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+        scratch: &mut Vec<F::Repr>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root_in(bits_index, element, scratch)
+            .is_ok_and(|root| digests_match(&root, root_hash.as_ref()))
+    }
+
+    pub fn validate<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.validate_get(bits_index, element, root_hash).is_some()
+    }
+
+    pub fn validate_leaf<F: PrimeField + PrimeFieldBits, L: Leaf<F>>(
+        &self,
+        bits_index: &[bool],
+        leaf: &L,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.validate_hash::<F>(bits_index, &leaf.hash_with(&H::default()), root_hash)
+    }
+
+    // Accepts any of `roots`, e.g. the last few published states. The root is computed once and
+    // compared against every candidate without stopping early, so timing does not reveal which
+    // one matched.
+    pub fn validate_any<F: PrimeField + PrimeFieldBits, R: AsRef<[u8]>>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+        roots: &[R],
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let Ok(root) = self.calculate_root(bits_index, element) else {
+            return false;
+        };
+        roots.iter().fold(false, |matched, candidate| {
+            matched | digests_match(&root, candidate.as_ref())
+        })
+    }
+
+    // Like `validate`, but hands back the verified root so it can feed a parent commitment
+    // without being recomputed.
+    pub fn validate_get<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> Option<Digest<N>>
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root(bits_index, element)
+            .ok()
+            .filter(|root| digests_match(root, root_hash.as_ref()))
+    }
+
+    // Patches this proof for `my_index` after a write at `other_index`, given that write's
+    // `get_path_hashes`. Only the sibling where the two paths diverge can change.
+    pub fn update(
+        &mut self,
+        my_index: &[bool],
+        other_index: &[bool],
+        other_new_path: &[Digest<N>],
+    ) -> Result<(), MerkleError> {
+        let height = self.sibling_hashes.len();
+        for len in [my_index.len(), other_index.len(), other_new_path.len()] {
+            if len != height {
+                return Err(MerkleError::LengthMismatch {
+                    expected: height,
+                    got: len,
+                });
+            }
+        }
+        let shared = my_index
+            .iter()
+            .zip(other_index)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if shared < height {
+            self.sibling_hashes[shared] = other_new_path[shared];
+        }
+        Ok(())
+    }
+
+    pub fn validate_at<F: PrimeField + PrimeFieldBits>(
+        &self,
+        index: u64,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        match convert_to_bits(self.sibling_hashes.len(), index) {
+            Ok(bits_index) => self.validate(bits_index, element, root_hash),
+            Err(_) => false,
+        }
+    }
+
+    pub fn validate_non_membership<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        empty_value: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.validate(bits_index, empty_value, root_hash)
+    }
+}
+
+impl<H: SortedPairs, const N: usize> Proof<H, N> {
+    // Sorted hashers ignore sides, so the path is folded without an index.
+    pub fn validate_sorted<F: PrimeField + PrimeFieldBits>(
+        &self,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let bits_index = vec![false; self.expected_height()];
+        self.validate(bits_index, element, root_hash)
+    }
+}
+
+// Everything by value in one flat call, for FFI wrappers. An index that does not fit `height`
+// fails verification rather than erroring.
+pub fn verify_owned<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    proof: Proof<H, N>,
+    index: u64,
+    element: Element<F>,
+    root: Vec<u8>,
+    height: usize,
+) -> bool {
+    convert_to_bits(height, index)
+        .is_ok_and(|bits_index| proof.validate(bits_index, &element, root))
+}
+
+// Checks a path through nested trees, innermost first: each link's element must be
+// `Element::from_bytes` of the root recomputed by the link before it, and the outermost link must
+// reach `final_root`.
+pub fn verify_chain<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    proofs: &[(Vec<bool>, Element<F>, Proof<H, N>)],
+    final_root: impl AsRef<[u8]>,
+) -> bool {
+    let mut scratch = Vec::new();
+    let mut child_root: Option<Digest<N>> = None;
+    for (bits_index, element, proof) in proofs {
+        if child_root.is_some_and(|root| *element != Element::from_bytes(&root)) {
+            return false;
+        }
+        match proof.calculate_root_in(bits_index, element, &mut scratch) {
+            Ok(root) => child_root = Some(root),
+            Err(_) => return false,
+        }
+    }
+    child_root.is_some_and(|root| digests_match(&root, final_root.as_ref()))
+}
+
+// Same answer as validating each item on its own. Every node a proof recomputes is remembered once
+// that proof reaches `root_hash`, so a later proof stops at the first ancestor it shares with an
+// earlier one instead of hashing all the way up.
+pub fn batch_verify<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    root_hash: impl AsRef<[u8]>,
+    items: &[(Vec<bool>, Element<F>, Proof<H, N>)],
+) -> bool {
+    let root_hash = root_hash.as_ref();
+    let hasher = H::default();
+    let mut scratch = Vec::new();
+    let mut verified = BTreeMap::<(usize, &[bool]), Digest<N>>::new();
+    for (bits_index, element, proof) in items {
+        let height = bits_index.len();
+        if height != proof.expected_height() || !proof.matches_directions(bits_index) {
+            return false;
+        }
+        let mut current_hash = element.compute_hash_in(&hasher, &mut scratch);
+        let mut path = Vec::with_capacity(height);
+        let mut depth = height;
+        while depth > 0 {
+            if let Some(known) = verified.get(&(height, &bits_index[..depth])) {
+                if *known != current_hash {
+                    return false;
+                }
+                break;
+            }
+            path.push((depth, current_hash));
+            let sibling = &proof.sibling_hashes[depth - 1];
+            current_hash = if bits_index[depth - 1] {
+                hasher.hash_nodes(sibling, &current_hash)
+            } else {
+                hasher.hash_nodes(&current_hash, sibling)
+            };
+            depth -= 1;
+        }
+        if depth == 0 && !digests_match(&current_hash, root_hash) {
+            return false;
+        }
+        for (depth, hash) in path {
+            verified.insert((height, &bits_index[..depth]), hash);
+        }
+    }
+    true
+}
+
+// Flat framing: the siblings back to back, `N` bytes each, with no length prefix. Directions
+// are not carried.
+impl<H, const N: usize> Proof<H, N> {
+    pub fn to_flat_bytes(&self) -> Vec<u8> {
+        self.sibling_hashes.concat()
+    }
+}
+
+impl<H, const N: usize> TryFrom<&[u8]> for Proof<H, N> {
+    type Error = MerkleError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, MerkleError> {
+        if bytes.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if !bytes.len().is_multiple_of(N) {
+            return Err(MerkleError::MisalignedDigests {
+                width: N,
+                len: bytes.len(),
+            });
+        }
+        let sibling_hashes = bytes
+            .chunks_exact(N)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(Proof::new(sibling_hashes))
+    }
+}
+
+// Siblings equal to the default hash of their level are dropped; bit `i` of `bitmap` is set when
+// `sibling_hashes[i]` of the full proof was kept.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct CompactProof<H = Sha256Hasher, const N: usize = 32> {
+    pub bitmap: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::digests"))]
+    pub sibling_hashes: Vec<Digest<N>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _marker: PhantomData<H>,
+}
+
+fn check_compact_levels<const N: usize>(
+    levels: usize,
+    default_hashes: &[Digest<N>],
+) -> Result<(), MerkleError> {
+    if levels > u32::BITS as usize {
+        return Err(MerkleError::LengthMismatch {
+            expected: u32::BITS as usize,
+            got: levels,
+        });
+    }
+    if default_hashes.len() != levels + 1 {
+        return Err(MerkleError::LengthMismatch {
+            expected: levels + 1,
+            got: default_hashes.len(),
+        });
+    }
+    Ok(())
+}
+
+impl<H, const N: usize> Proof<H, N> {
+    pub fn compress(
+        &self,
+        default_hashes: &[Digest<N>],
+    ) -> Result<CompactProof<H, N>, MerkleError> {
+        let levels = self.sibling_hashes.len();
+        check_compact_levels(levels, default_hashes)?;
+        let mut bitmap = 0;
+        let mut sibling_hashes = Vec::new();
+        for (i, sibling) in self.sibling_hashes.iter().enumerate() {
+            if *sibling != default_hashes[levels - 1 - i] {
+                bitmap |= 1 << i;
+                sibling_hashes.push(*sibling);
+            }
+        }
+        Ok(CompactProof {
+            bitmap,
+            sibling_hashes,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<H, const N: usize> CompactProof<H, N> {
+    // `default_hashes` must hold one entry per tree level plus the root, as returned by
+    // `default_hashes` for the tree's empty value and height.
+    pub fn expand(&self, default_hashes: &[Digest<N>]) -> Result<Proof<H, N>, MerkleError> {
+        let levels = self.check_levels(default_hashes)?;
+        let mut kept = self.sibling_hashes.iter();
+        let sibling_hashes = (0..levels)
+            .map(|i| {
+                if self.bitmap & (1 << i) != 0 {
+                    *kept.next().unwrap()
+                } else {
+                    default_hashes[levels - 1 - i]
+                }
+            })
+            .collect();
+        Ok(Proof::new(sibling_hashes))
+    }
+
+    // Number of tree levels, once the bitmap is known to fit them and to name every kept sibling.
+    fn check_levels(&self, default_hashes: &[Digest<N>]) -> Result<usize, MerkleError> {
+        let levels = default_hashes.len().saturating_sub(1);
+        check_compact_levels(levels, default_hashes)?;
+        let kept_count = (self.bitmap as u64 & ((1u64 << levels) - 1)).count_ones() as usize;
+        if kept_count != self.bitmap.count_ones() as usize
+            || kept_count != self.sibling_hashes.len()
+        {
+            return Err(MerkleError::LengthMismatch {
+                expected: kept_count,
+                got: self.sibling_hashes.len(),
+            });
+        }
+        Ok(levels)
+    }
+
+    // Agrees with `expand` followed by `Proof::validate`, but folds from the leaf taking each
+    // sibling from the kept hashes or the defaults as the bitmap says, so no full proof is built.
+    pub fn validate<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+        default_hashes: &[Digest<N>],
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let Ok(levels) = self.check_levels(default_hashes) else {
+            return false;
+        };
+        if bits_index.len() != levels {
+            return false;
+        }
+        let hasher = H::default();
+        let mut kept = self.sibling_hashes.iter().rev();
+        let mut current_hash = element.compute_hash_with(&hasher);
+        for (i, &direction) in bits_index.iter().enumerate().rev() {
+            let sibling = if self.bitmap & (1 << i) != 0 {
+                kept.next().unwrap()
+            } else {
+                &default_hashes[levels - 1 - i]
+            };
+            current_hash = if direction {
+                hasher.hash_nodes(sibling, &current_hash)
+            } else {
+                hasher.hash_nodes(&current_hash, sibling)
+            };
+        }
+        digests_match(&current_hash, root_hash.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn check_leaves() {
+        const HEIGHT: usize = 32;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+
+        for i in 0..50 {
+            let index = i;
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            let element = Element {
+                value: vec![Fp::random(&mut rand::thread_rng())],
+            };
+
+            let path_siblings = tree.get_sibling_hashes(&bits_index).unwrap();
+            let proof: Proof = Proof::new(path_siblings);
+            assert!(!proof.validate(bits_index.clone(), &element, tree.top));
+            tree.add_element(bits_index.clone(), &element).unwrap();
+            let new_path_siblings = tree.get_sibling_hashes(&bits_index).unwrap();
+            let new_proof: Proof = Proof::new(new_path_siblings);
+            assert!(new_proof.validate(bits_index, &element, tree.top));
+        }
+    }
+
+    #[test]
+    fn missing_node_is_an_error() {
+        const HEIGHT: usize = 4;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 3).unwrap();
+        tree.add_element(bits_index.clone(), &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        assert!(tree.get_sibling_hashes(&bits_index).is_ok());
+
+        tree.data_store.clear();
+        assert_eq!(
+            tree.get_sibling_hashes(&bits_index),
+            Err(MerkleError::MissingNode(tree.top.to_vec()))
+        );
+    }
+
+    #[test]
+    fn extra_path_bits_are_a_height_mismatch() {
+        const HEIGHT: usize = 4;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut bits_index = convert_to_bits(HEIGHT, 3).unwrap();
+        bits_index.push(true);
+        let expected = Err(MerkleError::HeightMismatch { height: HEIGHT });
+        assert_eq!(tree.get_sibling_hashes(&bits_index), expected);
+
+        tree.add_element_at(3, &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        assert_eq!(tree.get_sibling_hashes(&bits_index), expected);
+        let mut capped = MerkleTree::<Fp>::builder()
+            .height(HEIGHT)
+            .max_nodes(HEIGHT + 1)
+            .build()
+            .unwrap();
+        capped
+            .add_element_at(3, &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        assert_eq!(capped.get_sibling_hashes(&bits_index), expected);
+    }
+
+    #[test]
+    fn index_bounds() {
+        assert_eq!(convert_to_bits(0, 0), Ok(vec![]));
+        assert_eq!(
+            convert_to_bits(0, 1),
+            Err(MerkleError::IndexOutOfRange {
+                index: 1,
+                height: 0
+            })
+        );
+        assert_eq!(convert_to_bits(3, 5), Ok(vec![true, false, true]));
+        assert!(convert_to_bits(3, 8).is_err());
+
+        let high = 1u64 << 63;
+        assert!(convert_to_bits(63, high).is_err());
+        let bits = convert_to_bits(64, high | 1).unwrap();
+        assert_eq!(bits.len(), 64);
+        assert!(bits[0] && bits[63]);
+        assert!(bits[1..63].iter().all(|b| !b));
+        let bits = convert_to_bits(66, u64::MAX).unwrap();
+        assert!(!bits[0] && !bits[1] && bits[2..].iter().all(|b| *b));
+
+        const HEIGHT: usize = 4;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        assert_eq!(tree.height(), HEIGHT);
+        let element = Element {
+            value: vec![Fp::ONE],
+        };
+        assert_eq!(
+            tree.add_element(vec![true; HEIGHT - 1], &element),
+            Err(MerkleError::LengthMismatch {
+                expected: HEIGHT,
+                got: HEIGHT - 1
+            })
+        );
+        assert!(tree.add_element(vec![true; HEIGHT + 1], &element).is_err());
+        assert!(tree.add_element(vec![true; HEIGHT], &element).is_ok());
+    }
+
+    #[test]
+    fn get_element_returns_stored_leaf() {
+        const HEIGHT: usize = 6;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        assert!(tree.get_element(&bits_index).is_none());
+
+        let element = Element {
+            value: vec![Fp::from(7), Fp::from(8)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        assert_eq!(tree.get_element(&bits_index).unwrap().value, element.value);
+        assert!(tree
+            .get_element(&convert_to_bits(HEIGHT, 10).unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn remove_restores_empty_root() {
+        const HEIGHT: usize = 8;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let empty_root = tree.top;
+
+        let indices = [0, 1, 5, 200, 255];
+        for &index in &indices {
+            let element = Element {
+                value: vec![Fp::from(index + 1)],
+            };
+            tree.add_element(convert_to_bits(HEIGHT, index).unwrap(), &element)
+                .unwrap();
+        }
+        assert_ne!(tree.top, empty_root);
+
+        for &index in &indices {
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            tree.remove_element(bits_index.clone()).unwrap();
+            assert!(tree.get_element(&bits_index).is_none());
+            let proof = tree.prove(&bits_index).unwrap();
+            assert!(proof.validate(bits_index, &Element::<Fp>::default(), tree.top));
+        }
+        assert_eq!(tree.top, empty_root);
+    }
+
+    #[test]
+    fn prove_with_root_verifies() {
+        const HEIGHT: usize = 10;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 77).unwrap();
+        let element = Element {
+            value: vec![Fp::from(42)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+
+        let (proof, root) = tree.prove_with_root(&bits_index).unwrap();
+        assert_eq!(root, tree.top);
+        assert_eq!(proof.sibling_hashes.len(), HEIGHT);
+        assert!(proof.validate(bits_index.clone(), &element, root));
+        assert!(tree.prove(&bits_index[1..]).is_err());
+    }
+
+    #[test]
+    fn add_batch_matches_sequential_inserts() {
+        const HEIGHT: usize = 16;
+        let mut sequential = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut batched = sequential.clone();
+
+        let mut entries = Vec::new();
+        for index in [9u64, 0, 65535, 10, 8, 32768, 9] {
+            let element = Element {
+                value: vec![Fp::from(index), Fp::from(entries.len() as u64)],
+            };
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            sequential
+                .add_element(bits_index.clone(), &element)
+                .unwrap();
+            entries.push((bits_index, element));
+        }
+        batched.add_batch(entries.clone()).unwrap();
+        assert_eq!(batched.top, sequential.top);
+
+        for (bits_index, _) in &entries {
+            let element = batched.get_element(bits_index).unwrap();
+            let proof = batched.prove(bits_index).unwrap();
+            assert!(proof.validate(bits_index.clone(), &element, batched.top));
+        }
+        let last = &entries[entries.len() - 1];
+        assert_eq!(batched.get_element(&last.0).unwrap().value, last.1.value);
+
+        let before = batched.top;
+        batched.add_batch(Vec::new()).unwrap();
+        assert!(batched
+            .add_batch(vec![(vec![false; HEIGHT - 1], Element::default())])
+            .is_err());
+        assert_eq!(batched.top, before);
+    }
+
+    #[test]
+    fn non_membership_proofs() {
+        const HEIGHT: usize = 10;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 300).unwrap();
+
+        let proof = tree.prove_non_membership(&bits_index).unwrap();
+        assert!(proof.validate_non_membership(bits_index.clone(), &empty_element, tree.top));
+
+        let element = Element {
+            value: vec![Fp::from(5)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        assert_eq!(
+            tree.prove_non_membership(&bits_index).unwrap_err(),
+            MerkleError::LeafOccupied
+        );
+        let proof = tree.prove(&bits_index).unwrap();
+        assert!(!proof.validate_non_membership(bits_index.clone(), &empty_element, tree.top));
+
+        tree.remove_element(bits_index.clone()).unwrap();
+        let proof = tree.prove_non_membership(&bits_index).unwrap();
+        assert!(proof.validate_non_membership(bits_index.clone(), &empty_element, tree.top));
+        assert!(!proof.validate(bits_index, &element, tree.top));
+    }
+
+    #[test]
+    fn default_hashes_per_level() {
+        const HEIGHT: usize = 5;
+        let empty_element = Element::<Fp>::default();
+        let tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+        assert_eq!(*tree.default_hash_at_level(0), empty_element.compute_hash());
+        assert_eq!(*tree.default_hash_at_level(HEIGHT), tree.top);
+        assert!(tree.data_store.is_empty());
+        let path: Vec<Digest> = (0..HEIGHT)
+            .rev()
+            .map(|level| *tree.default_hash_at_level(level))
+            .collect();
+        assert_eq!(tree.get_path_hashes(&[true; HEIGHT]).unwrap(), path);
+    }
+
+    #[test]
+    fn compact_proofs_drop_default_siblings() {
+        const HEIGHT: usize = 32;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+        let defaults = default_hashes(&Sha256Hasher, &empty_element, HEIGHT);
+
+        let bits_index = convert_to_bits(HEIGHT, 0).unwrap();
+        let element = Element {
+            value: vec![Fp::from(1)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let compact = tree.prove_compact(&bits_index).unwrap();
+        assert_eq!(compact.bitmap, 0);
+        assert!(compact.sibling_hashes.is_empty());
+        assert!(compact.validate(bits_index.clone(), &element, tree.top, &defaults));
+
+        let neighbour = convert_to_bits(HEIGHT, 1).unwrap();
+        tree.add_element(neighbour, &element).unwrap();
+        let compact = tree.prove_compact(&bits_index).unwrap();
+        assert_eq!(compact.bitmap, 1 << (HEIGHT - 1));
+        assert_eq!(compact.sibling_hashes.len(), 1);
+        let full = compact.expand(&defaults).unwrap();
+        assert_eq!(
+            full.sibling_hashes,
+            tree.prove(&bits_index).unwrap().sibling_hashes
+        );
+        assert!(compact.validate(bits_index.clone(), &element, tree.top, &defaults));
+        assert!(!compact.validate(bits_index, &empty_element, tree.top, &defaults));
+        assert!(!compact.validate(vec![false; 4], &element, tree.top, &defaults));
+    }
+
+    #[test]
+    fn compact_validate_agrees_with_expanding() {
+        const HEIGHT: usize = 5;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+        let defaults = default_hashes(&Sha256Hasher, &empty_element, HEIGHT);
+        for index in [0, 1, 6, 19, 31] {
+            tree.add_element_at(index, &Element::single(Fp::from(index)))
+                .unwrap();
+        }
+        let expanded = |compact: &CompactProof, bits_index: &[bool], element, root| {
+            compact
+                .expand(&defaults)
+                .is_ok_and(|proof| proof.validate(bits_index.to_vec(), element, root))
+        };
+        let elements = [empty_element.clone(), Element::single(Fp::from(6))];
+        for index in 0..1 << HEIGHT {
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            let compact = tree.prove_compact(&bits_index).unwrap();
+            let mut flipped = compact.clone();
+            flipped.bitmap ^= 1 << (index % HEIGHT as u64);
+            let mut extra = compact.clone();
+            extra.sibling_hashes.push(tree.top);
+            for proof in [&compact, &flipped, &extra] {
+                for element in &elements {
+                    for bits in [&bits_index[..], &bits_index[1..]] {
+                        assert_eq!(
+                            proof.validate(bits.to_vec(), element, tree.top, &defaults),
+                            expanded(proof, bits, element, tree.top)
+                        );
+                    }
+                }
+            }
+            let element = tree.get_element(&bits_index).unwrap_or_default();
+            assert!(compact.validate(bits_index, &element, tree.top, &defaults));
+        }
+    }
+
+    #[test]
+    fn overwrites_do_not_grow_the_store() {
+        const HEIGHT: usize = 16;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 1234).unwrap();
+        for i in 0..100 {
+            let element = Element {
+                value: vec![Fp::from(i)],
+            };
+            tree.add_element(bits_index.clone(), &element).unwrap();
+            assert!(tree.data_store.len() <= 4 * HEIGHT);
+        }
+
+        tree.prune();
+        assert_eq!(tree.data_store.len(), HEIGHT);
+        let element = tree.get_element(&bits_index).unwrap();
+        assert!(tree
+            .prove(&bits_index)
+            .unwrap()
+            .validate(bits_index, &element, tree.top));
+        let empty = tree.prove(&convert_to_bits(HEIGHT, 0).unwrap()).unwrap();
+        assert!(empty.validate(
+            convert_to_bits(HEIGHT, 0).unwrap(),
+            &Element::<Fp>::default(),
+            tree.top
+        ));
+    }
+
+    #[test]
+    fn index_based_api() {
+        const HEIGHT: usize = 12;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let element = Element {
+            value: vec![Fp::from(3)],
+        };
+        tree.add_element_at(4000, &element).unwrap();
+        assert_eq!(
+            tree.add_element_at(4096, &element),
+            Err(MerkleError::IndexOutOfRange {
+                index: 4096,
+                height: HEIGHT
+            })
+        );
+
+        let proof = tree.prove_at(4000).unwrap();
+        assert_eq!(
+            proof.sibling_hashes,
+            tree.prove(&convert_to_bits(HEIGHT, 4000).unwrap())
+                .unwrap()
+                .sibling_hashes
+        );
+        assert!(proof.validate_at(4000, &element, tree.top));
+        assert!(!proof.validate_at(4001, &element, tree.top));
+        assert!(!proof.validate_at(1 << HEIGHT, &element, tree.top));
+        assert!(tree.prove_at(1 << HEIGHT).is_err());
+    }
+
+    #[test]
+    fn proofs_follow_later_writes() {
+        const HEIGHT: usize = 10;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mine = convert_to_bits(HEIGHT, 600).unwrap();
+        let element = Element {
+            value: vec![Fp::from(600)],
+        };
+        tree.add_element(mine.clone(), &element).unwrap();
+        let mut proof = tree.prove(&mine).unwrap();
+
+        for index in [601u64, 0, 1023, 512, 600] {
+            let other = convert_to_bits(HEIGHT, index).unwrap();
+            let other_element = if index == 600 {
+                element.clone()
+            } else {
+                Element {
+                    value: vec![Fp::from(index + 7)],
+                }
+            };
+            tree.add_element(other.clone(), &other_element).unwrap();
+            let path = tree.get_path_hashes(&other).unwrap();
+            assert_eq!(path[HEIGHT - 1], other_element.compute_hash());
+            proof.update(&mine, &other, &path).unwrap();
+            assert!(proof.validate(mine.clone(), &element, tree.top));
+            assert_eq!(
+                proof.sibling_hashes,
+                tree.prove(&mine).unwrap().sibling_hashes
+            );
+        }
+        assert!(proof.update(&mine, &mine[1..], &[]).is_err());
+    }
+
+    #[test]
+    fn mismatched_proof_length_is_an_error() {
+        const HEIGHT: usize = 8;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        let element = Element {
+            value: vec![Fp::from(9)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert_eq!(
+            proof.calculate_root(bits_index.clone(), &element),
+            Ok(tree.top)
+        );
+
+        let mut longer = bits_index.clone();
+        longer.push(false);
+        assert_eq!(
+            proof.calculate_root(longer.clone(), &element),
+            Err(MerkleError::LengthMismatch {
+                expected: HEIGHT,
+                got: HEIGHT + 1
+            })
+        );
+        assert!(!proof.validate(longer, &element, tree.top));
+        assert!(proof
+            .calculate_root(bits_index[1..].to_vec(), &element)
+            .is_err());
+        assert!(!proof.validate(bits_index[1..].to_vec(), &element, tree.top));
+
+        let mut truncated = proof.clone();
+        truncated.sibling_hashes.pop();
+        assert!(truncated
+            .calculate_root(bits_index.clone(), &element)
+            .is_err());
+        assert!(!truncated.validate(bits_index, &element, tree.top));
+    }
+
+    #[test]
+    fn append_fills_positions_in_order() {
+        const HEIGHT: usize = 3;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for expected in 0..8u64 {
+            let element = Element {
+                value: vec![Fp::from(expected + 100)],
+            };
+            assert_eq!(tree.append(&element), Ok(expected));
+            assert!(tree
+                .prove_at(expected)
+                .unwrap()
+                .validate_at(expected, &element, tree.top));
+        }
+        assert_eq!(tree.next_index(), 8);
+        assert_eq!(tree.append(&Element::default()), Err(MerkleError::TreeFull));
+        // A write behind the cursor replaces that slot and leaves the cursor alone.
+        let element = Element::single(Fp::from(42));
+        tree.add_element_at(2, &element).unwrap();
+        assert_eq!(tree.next_index(), 8);
+        assert_eq!(
+            tree.get_element(&convert_to_bits(HEIGHT, 2).unwrap()),
+            Some(element)
+        );
+
+        let mut empty = MerkleTree::<Fp>::initialize(Element::default(), 0);
+        assert_eq!(empty.append(&Element::default()), Ok(0));
+        assert_eq!(
+            empty.append(&Element::default()),
+            Err(MerkleError::TreeFull)
+        );
+    }
+
+    #[test]
+    fn tree_from_elements() {
+        const HEIGHT: usize = 4;
+        let elements: Vec<Element<Fp>> = (0..6)
+            .map(|i| Element {
+                value: vec![Fp::from(i * 10)],
+            })
+            .collect();
+        let mut tree =
+            MerkleTree::<Fp>::from_elements(Element::default(), HEIGHT, elements.clone()).unwrap();
+
+        let mut sequential = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for element in &elements {
+            sequential.append(element).unwrap();
+        }
+        assert_eq!(tree.top, sequential.top);
+        assert!(tree
+            .prove_at(3)
+            .unwrap()
+            .validate_at(3, &elements[3], tree.top));
+        assert_eq!(tree.append(&Element::default()), Ok(6));
+
+        let too_many = (0..17).map(|_| Element::<Fp>::default());
+        assert_eq!(
+            MerkleTree::<Fp>::from_elements(Element::default(), HEIGHT, too_many).unwrap_err(),
+            MerkleError::TreeFull
+        );
+    }
+
+    #[test]
+    fn contains_tracks_occupied_leaves() {
+        const HEIGHT: usize = 6;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 33).unwrap();
+        assert!(!tree.contains(&bits_index));
+
+        let element = Element {
+            value: vec![Fp::from(2)],
+        };
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        assert!(tree.contains(&bits_index));
+        tree.remove_element(bits_index.clone()).unwrap();
+        assert!(!tree.contains(&bits_index));
+
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        tree.add_element(bits_index.clone(), &empty_element)
+            .unwrap();
+        assert!(!tree.contains(&bits_index));
+        assert!(tree.get_element(&bits_index).is_none());
+
+        tree.add_batch(vec![(bits_index.clone(), empty_element)])
+            .unwrap();
+        assert!(!tree.contains(&bits_index));
+        assert!(!tree.contains(&bits_index[1..]));
+    }
+
+    #[test]
+    fn len_counts_occupied_leaves() {
+        const HEIGHT: usize = 5;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        assert!(tree.is_empty());
+
+        for index in [3, 7, 3] {
+            let element = Element {
+                value: vec![Fp::from(index + 1)],
+            };
+            tree.add_element_at(index, &element).unwrap();
+        }
+        assert_eq!(tree.len(), 2);
+        assert!(!tree.is_empty());
+
+        tree.remove_element(convert_to_bits(HEIGHT, 3).unwrap())
+            .unwrap();
+        assert_eq!(tree.len(), 1);
+        tree.remove_element(convert_to_bits(HEIGHT, 3).unwrap())
+            .unwrap();
+        assert_eq!(tree.len(), 1);
+        tree.remove_element(convert_to_bits(HEIGHT, 7).unwrap())
+            .unwrap();
+        assert!(tree.is_empty());
+    }
+
+    // Truncated SHA-256, to exercise a digest width other than the default.
+    #[derive(Clone, Default)]
+    struct ShortHasher;
+
+    impl Hasher<Fp, 16> for ShortHasher {
+        fn hash_leaf(&self, values: &[[u8; 32]]) -> Digest<16> {
+            let digest = Hasher::<Fp>::hash_leaf(&Sha256Hasher, values);
+            digest[..16].try_into().unwrap()
+        }
+
+        fn hash_nodes(&self, left: &Digest<16>, right: &Digest<16>) -> Digest<16> {
+            let mut digest = [0; 32];
+            digest[..16].copy_from_slice(left);
+            digest[16..].copy_from_slice(right);
+            let digest = Hasher::<Fp>::hash_nodes(&Sha256Hasher, &digest, &[0; 32]);
+            digest[..16].try_into().unwrap()
+        }
+    }
+
+    #[test]
+    fn leaf_hash_uses_the_tree_hasher() {
+        let element = Element::new(vec![Fp::from(4)]).unwrap();
+        let bits_index = convert_to_bits(3, 4).unwrap();
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        assert_eq!(tree.leaf_hash(&element), element.compute_hash());
+        assert_eq!(
+            tree.subtree_root(&bits_index).unwrap(),
+            tree.leaf_hash(&element)
+        );
+
+        let mut short = MerkleTree::<Fp, ShortHasher, 16>::initialize(Element::default(), 3);
+        short.add_element(bits_index.clone(), &element).unwrap();
+        assert_eq!(
+            short.subtree_root(&bits_index).unwrap(),
+            short.leaf_hash(&element)
+        );
+        assert_eq!(short.leaf_hash(&element)[..], element.compute_hash()[..16]);
+    }
+
+    #[test]
+    fn fingerprints_ignore_the_hasher() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 6);
+        let mut short = MerkleTree::<Fp, ShortHasher, 16>::initialize(Element::default(), 6);
+        assert_eq!(tree.content_fingerprint(), short.content_fingerprint());
+        for index in [40, 3, 17] {
+            let element = Element::new(vec![Fp::from(index), Fp::ONE]).unwrap();
+            tree.add_element_at(index, &element).unwrap();
+            short.add_element_at(index, &element).unwrap();
+        }
+        assert_eq!(tree.content_fingerprint(), short.content_fingerprint());
+
+        short
+            .add_element_at(5, &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        assert_ne!(tree.content_fingerprint(), short.content_fingerprint());
+        let taller = MerkleTree::<Fp>::initialize(Element::default(), 7);
+        let empty = MerkleTree::<Fp>::initialize(Element::default(), 6);
+        assert_ne!(taller.content_fingerprint(), empty.content_fingerprint());
+    }
+
+    #[test]
+    fn sorted_pairs_need_no_directions() {
+        use crate::hasher::SortedSha256Hasher;
+        const HEIGHT: usize = 5;
+        let mut sorted =
+            MerkleTree::<Fp, SortedSha256Hasher>::initialize(Element::default(), HEIGHT);
+        let mut positional = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in [2, 9, 30] {
+            let element = Element::new(vec![Fp::from(index)]).unwrap();
+            sorted.add_element_at(index, &element).unwrap();
+            positional.add_element_at(index, &element).unwrap();
+        }
+        let element = Element::new(vec![Fp::from(9)]).unwrap();
+        let proof = sorted.prove_at(9).unwrap();
+        assert!(proof.validate_sorted(&element, sorted.top));
+        assert!(proof.validate_at(9, &element, sorted.top));
+        assert!(!proof.validate_sorted(&element, positional.top));
+
+        let positional_proof = positional.prove_at(9).unwrap();
+        let relabelled: Proof<SortedSha256Hasher> = Proof::new(positional_proof.sibling_hashes);
+        assert!(!relabelled.validate_at(9, &element, positional.top));
+    }
+
+    #[test]
+    fn children_walk_to_every_leaf() {
+        const HEIGHT: usize = 4;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in [1, 6, 15] {
+            tree.add_element_at(index, &Element::new(vec![Fp::from(index)]).unwrap())
+                .unwrap();
+        }
+        let mut level = vec![tree.top];
+        for _ in 0..HEIGHT {
+            level = level
+                .iter()
+                .flat_map(|node| {
+                    let (left, right) = tree.children(node).unwrap();
+                    [*left, *right]
+                })
+                .collect();
+        }
+        let leaves: Vec<Digest> = (0..16)
+            .map(|index| {
+                tree.subtree_root(&convert_to_bits(HEIGHT, index).unwrap())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(level, leaves);
+        assert!(tree.children(&leaves[1]).is_none());
+        assert!(tree.children(&[0; 32]).is_none());
+        assert!(tree.children(&tree.top[..31]).is_none());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn hash_counts_per_operation() {
+        const HEIGHT: usize = 12;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let element = Element::new(vec![Fp::ONE]).unwrap();
+        tree.add_element_at(5, &element).unwrap();
+        assert_eq!(tree.last_op_hash_count(), HEIGHT);
+
+        let entries: Vec<_> = (0..4)
+            .map(|index| (convert_to_bits(HEIGHT, index).unwrap(), element.clone()))
+            .collect();
+        tree.add_batch(entries).unwrap();
+        // Leaves 0..4 share all but their bottom two levels: 2 + 1 + (HEIGHT - 2).
+        assert_eq!(tree.last_op_hash_count(), HEIGHT + 1);
+
+        let bits_index = convert_to_bits(HEIGHT, 5).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert_eq!(tree.last_op_hash_count(), 0);
+        let mut calls = 0;
+        let root = proof
+            .calculate_root_with_hook(&bits_index, &element, &mut || calls += 1)
+            .unwrap();
+        assert_eq!((root, calls), (tree.top, HEIGHT));
+    }
+
+    #[test]
+    fn flat_bytes_round_trip() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 7);
+        tree.add_element_at(3, &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        let proof = tree.prove_at(3).unwrap();
+        let bytes = proof.to_flat_bytes();
+        assert_eq!(bytes.len(), 7 * 32);
+        assert_eq!(Proof::try_from(&bytes[..]), Ok(proof));
+        assert_eq!(
+            Proof::<Sha256Hasher>::try_from(&bytes[..0]),
+            Err(MerkleError::EmptyProof)
+        );
+        assert_eq!(
+            Proof::<Sha256Hasher>::try_from(&bytes[1..]),
+            Err(MerkleError::MisalignedDigests {
+                width: 32,
+                len: 7 * 32 - 1
+            })
+        );
+        let short: Proof<ShortHasher, 16> = Proof::try_from(&bytes[..]).unwrap();
+        assert_eq!(short.expected_height(), 14);
+    }
+
+    #[test]
+    fn empty_root_matches_a_fresh_tree() {
+        let one = Element::new(vec![Fp::ONE]).unwrap();
+        for height in [0, 1, 20, 256] {
+            let tree = MerkleTree::<Fp>::initialize(one.clone(), height);
+            assert_eq!(empty_root(&one, height), tree.top);
+        }
+        let short = MerkleTree::<Fp, ShortHasher, 16>::initialize(Element::default(), 9);
+        assert_eq!(
+            empty_root_with(&ShortHasher, &Element::default(), 9),
+            short.top
+        );
+    }
+
+    #[test]
+    fn domains_separate_equal_leaves() {
+        let mut a = MerkleTree::<Fp>::initialize_with_domain(Element::default(), 4, b"tree a");
+        let mut b = MerkleTree::<Fp>::initialize_with_domain(Element::default(), 4, b"tree b");
+        let plain = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        assert_eq!(a.domain(), Some(&b"tree a"[..]));
+        assert_ne!(a.top, b.top);
+        assert_ne!(a.top, plain.top);
+
+        let element = Element::new(vec![Fp::from(9)]).unwrap();
+        let bits_index = convert_to_bits(4, 5).unwrap();
+        a.insert(bits_index.clone(), &element).unwrap();
+        b.insert(bits_index.clone(), &element).unwrap();
+        let proof = a.prove(&bits_index).unwrap();
+        assert!(proof.validate_in_domain(&bits_index, &element, b"tree a", a.top));
+        assert!(!proof.validate_in_domain(&bits_index, &element, b"tree a", b.top));
+        assert!(!proof.validate_in_domain(&bits_index, &element, b"tree b", b.top));
+        assert!(!proof.validate(bits_index.clone(), &element, a.top));
+        assert_eq!(
+            a.leaf_hash(&element),
+            element.compute_hash_with_domain(b"tree a")
+        );
+        let built = MerkleTree::<Fp>::builder()
+            .height(4)
+            .domain(b"tree b")
+            .build()
+            .unwrap();
+        assert_eq!(
+            built.top,
+            MerkleTree::<Fp>::initialize_with_domain(Element::default(), 4, b"tree b").top
+        );
+    }
+
+    #[test]
+    fn unchecked_variants_agree_and_panic_on_bad_input() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let element = Element::new(vec![Fp::from(4)]).unwrap();
+        let bits_index = convert_to_bits(3, 6).unwrap();
+        tree.add_element_unchecked(bits_index.clone(), &element);
+        let proof = Proof::<Sha256Hasher>::new(tree.get_sibling_hashes_unchecked(&bits_index));
+        assert_eq!(
+            proof.calculate_root_unchecked(bits_index.clone(), &element),
+            tree.top
+        );
+
+        #[cfg(feature = "std")]
+        {
+            let short = bits_index[1..].to_vec();
+            let panicked =
+                std::panic::catch_unwind(|| proof.calculate_root_unchecked(short, &element));
+            assert!(panicked.is_err());
+        }
+    }
+
+    #[test]
+    fn borrowed_values_hash_like_their_element() {
+        let values: Vec<Fp> = (0..300).map(Fp::from).collect();
+        let element = Element::new(values.clone()).unwrap();
+        assert_eq!(Element::hash_values(&values), element.compute_hash());
+        let mut scratch = Vec::new();
+        assert_eq!(
+            Element::hash_values_in(&values[..10], &Sha256Hasher, &mut scratch),
+            Element::new(values[..10].to_vec()).unwrap().compute_hash()
+        );
+    }
+
+    #[test]
+    fn elements_convert_from_field_values() {
+        let x = Fp::from(42);
+        assert_eq!(Element::single(x), Element::new(vec![x]).unwrap());
+        assert_eq!(Element::from(x), Element::single(x));
+        assert_eq!(Element::from(vec![x, x]).value, vec![x, x]);
+    }
+
+    #[test]
+    fn non_canonical_reprs_are_refused() {
+        let x = Fp::from(42);
+        assert_eq!(
+            Element::<Fp>::from_reprs(&[x.to_repr(), (-x).to_repr()])
+                .unwrap()
+                .value,
+            vec![x, -x]
+        );
+        assert_eq!(
+            Element::<Fp>::from_reprs(&[[0xff; 32]]),
+            Err(MerkleError::NonCanonicalField)
+        );
+        // The modulus itself is the smallest non-canonical repr.
+        let mut modulus = (-Fp::ONE).to_repr();
+        modulus[0] += 1;
+        assert_eq!(
+            Element::<Fp>::from_reprs(&[modulus]),
+            Err(MerkleError::NonCanonicalField)
+        );
+        assert_eq!(
+            Element::<Fp>::from_reprs(&[[0u8; 31]]),
+            Err(MerkleError::NonCanonicalField)
+        );
+        assert_eq!(
+            Element::<Fp>::from_reprs::<[u8; 32]>(&[]),
+            Err(MerkleError::EmptyElement)
+        );
+    }
+
+    #[test]
+    fn empty_element_is_retained() {
+        let empty = Element::single(Fp::from(3));
+        let mut tree = MerkleTree::<Fp>::initialize(empty.clone(), 4);
+        tree.add_element_at(2, &Element::single(Fp::from(5)))
+            .unwrap();
+        assert_eq!(tree.empty_element(), &empty);
+        assert_eq!(
+            tree.leaf_hash(tree.empty_element()),
+            *tree.default_hash_at_level(0)
+        );
+        let rebuilt = MerkleTree::<Fp>::initialize(tree.empty_element().clone(), tree.height());
+        assert_eq!(rebuilt.top, empty_root(&empty, 4));
+    }
+
+    #[test]
+    fn predicted_roots_match_the_insert() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 5);
+        for (index, value) in [(3u64, 1u64), (17, 2), (3, 3)] {
+            let bits_index = convert_to_bits(5, index).unwrap();
+            let element = Element::single(Fp::from(value));
+            let before = tree.top;
+            let predicted = tree.predict_root(&bits_index, &element).unwrap();
+            assert_eq!(tree.top, before);
+            tree.add_element(bits_index, &element).unwrap();
+            assert_eq!(tree.top, predicted);
+        }
+        assert!(tree.predict_root(&[true], &Element::default()).is_err());
+    }
+
+    #[test]
+    fn eviction_keeps_recently_walked_paths() {
+        const HEIGHT: usize = 8;
+        let mut capped = MerkleTree::<Fp>::builder()
+            .height(HEIGHT)
+            .max_nodes(48)
+            .build()
+            .unwrap();
+        let mut plain = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let write = |trees: [&mut MerkleTree<Fp>; 2], index: u64, value: u64| {
+            for tree in trees {
+                tree.add_element_at(index, &Element::single(Fp::from(value)))
+                    .unwrap();
+            }
+        };
+        // Fill the top levels, so keeping the nodes nearest the root would drop the deep path.
+        for i in 0..64 {
+            write([&mut capped, &mut plain], i * 4, i + 1);
+        }
+        write([&mut capped, &mut plain], 0, 100);
+        let bits_index = convert_to_bits(HEIGHT, 0).unwrap();
+        for k in 0..10 {
+            capped.prove(&bits_index).unwrap();
+            write([&mut capped, &mut plain], 255 - 2 * k, k + 1);
+        }
+        let path = plain.get_path_hashes(&bits_index).unwrap();
+        for node_hash in &path[..HEIGHT - 1] {
+            assert!(capped.data_store.contains_key(node_hash));
+        }
+        assert!(capped.data_store.len() <= 48);
+        assert_eq!(capped.top, plain.top);
+    }
+
+    #[test]
+    fn capped_store_gives_the_same_roots_and_proofs() {
+        const HEIGHT: usize = 8;
+        let mut capped = MerkleTree::<Fp>::builder()
+            .height(HEIGHT)
+            .max_nodes(24)
+            .build()
+            .unwrap();
+        let mut plain = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        assert_eq!(capped.max_nodes(), Some(24));
+        for i in 0..120u64 {
+            let index = (i * 97 + 13) % (1 << HEIGHT);
+            let element = Element::single(Fp::from(i + 1));
+            capped.add_element_at(index, &element).unwrap();
+            plain.add_element_at(index, &element).unwrap();
+            assert_eq!(capped.top, plain.top);
+            assert!(capped.data_store.len() <= 24);
+        }
+        let batch: Vec<_> = (0..40u64)
+            .map(|i| {
+                (
+                    convert_to_bits(HEIGHT, i * 5).unwrap(),
+                    Element::single(Fp::from(i)),
+                )
+            })
+            .collect();
+        capped.add_batch(batch.clone()).unwrap();
+        plain.add_batch(batch).unwrap();
+        assert_eq!(capped.top, plain.top);
+
+        for index in 0..1 << HEIGHT {
+            assert_eq!(
+                capped.prove_at(index).unwrap(),
+                plain.prove_at(index).unwrap()
+            );
+        }
+        let indices: Vec<_> = (0..9)
+            .map(|i| convert_to_bits(HEIGHT, i * 29).unwrap())
+            .collect();
+        assert_eq!(
+            capped.prove_many(&indices).unwrap().sibling_hashes,
+            plain.prove_many(&indices).unwrap().sibling_hashes
+        );
+        assert_eq!(capped.diff(&plain).unwrap(), Vec::<u64>::new());
+
+        let bits_index = convert_to_bits(HEIGHT, 5).unwrap();
+        let snapshot = capped.snapshot();
+        assert_eq!(
+            snapshot.prove(&bits_index).unwrap(),
+            plain.prove(&bits_index).unwrap()
+        );
+        let restored = MerkleTree::<Fp>::deserialize(&capped.serialize(), HEIGHT).unwrap();
+        assert_eq!(
+            restored.prove(&bits_index).unwrap(),
+            plain.prove(&bits_index).unwrap()
+        );
+    }
+
+    #[test]
+    fn rebuilding_restores_a_tampered_store() {
+        let elements: Vec<_> = (1..=6).map(|i| Element::single(Fp::from(i))).collect();
+        let mut tree = MerkleTree::<Fp>::from_elements(Element::default(), 4, elements).unwrap();
+        let expected = tree.top;
+        let nodes = tree.data_store.clone();
+
+        let (left, _) = tree.data_store[&tree.top];
+        tree.data_store.insert(left, ([1; 32], [2; 32]));
+        tree.top = [3; 32];
+        assert_eq!(tree.rebuild_from_leaves(), expected);
+        assert_eq!(tree.top, expected);
+        assert_eq!(tree.data_store, nodes);
+        let bits_index = convert_to_bits(4, 2).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert!(proof.validate(bits_index, &Element::single(Fp::from(3)), expected));
+    }
+
+    #[test]
+    fn digest_width_follows_the_hasher() {
+        const HEIGHT: usize = 10;
+        let mut tree = MerkleTree::<Fp, ShortHasher, 16>::initialize(Element::default(), HEIGHT);
+        let element = Element {
+            value: vec![Fp::from(9)],
+        };
+        tree.add_element_at(700, &element).unwrap();
+        let proof = tree.prove_at(700).unwrap();
+        assert_eq!(proof.sibling_hashes.len(), HEIGHT);
+        assert!(proof.validate_at(700, &element, tree.top));
+        assert!(!proof.validate_at(701, &element, tree.top));
+    }
+
+    #[test]
+    fn element_bytes_round_trip() {
+        for len in [0, 1, 30, 31, 32, 62, 100] {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 255) as u8).collect();
+            let element = Element::<Fp>::from_bytes(&bytes);
+            assert_eq!(element.value.len(), len / 31 + 1);
+            assert_eq!(element.to_bytes(), bytes);
+        }
+
+        let zero = Element::<Fp>::from_bytes(&[0]);
+        assert_ne!(zero.value, Element::<Fp>::from_bytes(&[]).value);
+        assert_ne!(zero.value, Element::<Fp>::from_bytes(&[0, 0]).value);
+        assert_eq!(
+            Element::<Fp>::from_bytes(&[0xff; 31]).value[0],
+            Fp::from_u128(u128::MAX) * Fp::from_u128(1 << 120) + Fp::from_u128((1 << 120) - 1)
+        );
+    }
+
+    #[test]
+    fn empty_elements_are_rejected() {
+        const HEIGHT: usize = 4;
+        assert_eq!(
+            Element::<Fp>::new(vec![]).unwrap_err(),
+            MerkleError::EmptyElement
+        );
+        assert!(Element::new(vec![Fp::ZERO]).is_ok());
+
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let empty = Element { value: vec![] };
+        let bits_index = convert_to_bits(HEIGHT, 2).unwrap();
+        assert_eq!(
+            tree.add_element(bits_index.clone(), &empty),
+            Err(MerkleError::EmptyElement)
+        );
+        assert_eq!(tree.append(&empty), Err(MerkleError::EmptyElement));
+        assert_eq!(tree.next_index(), 0);
+        assert_eq!(
+            tree.add_batch(vec![(bits_index, empty)]),
+            Err(MerkleError::EmptyElement)
+        );
+        assert!(tree.is_empty());
+        assert_eq!(tree.top, *tree.default_hash_at_level(HEIGHT));
+    }
+
+    #[test]
+    fn root_history_evicts_the_oldest_root() {
+        const HEIGHT: usize = 6;
+        let mut tree = MerkleTree::<Fp>::initialize_with_history(Element::default(), HEIGHT, 3);
+        let empty_root = tree.top;
+        assert_eq!(tree.recent_roots(), &[empty_root]);
+
+        let mut roots = Vec::new();
+        for i in 0..3 {
+            tree.append(&Element {
+                value: vec![Fp::from(i + 1)],
+            })
+            .unwrap();
+            roots.push(tree.top);
+        }
+        assert_eq!(tree.recent_roots(), &roots);
+        assert!(!tree.is_known_root(empty_root));
+        assert!(tree.is_known_root(roots[0]));
+
+        let bits_index = convert_to_bits(HEIGHT, 1).unwrap();
+        tree.add_element(bits_index.clone(), &tree.get_element(&bits_index).unwrap())
+            .unwrap();
+        assert_eq!(tree.recent_roots(), &roots);
+
+        tree.remove_element(bits_index).unwrap();
+        assert!(!tree.is_known_root(roots[0]));
+        assert_eq!(tree.recent_roots(), &[roots[1], roots[2], tree.top]);
+
+        let untracked = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        assert!(untracked.recent_roots().is_empty());
+        assert!(untracked.is_known_root(empty_root));
+    }
+
+    #[test]
+    fn rollback_restores_an_earlier_state() {
+        const HEIGHT: usize = 8;
+        let mut tree = MerkleTree::<Fp>::initialize_with_history(Element::default(), HEIGHT, 4);
+        let element = |i: u64| Element {
+            value: vec![Fp::from(i + 1)],
+        };
+        tree.append(&element(0)).unwrap();
+        let checkpoint = tree.top;
+        let bits_index = convert_to_bits(HEIGHT, 0).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+
+        tree.append(&element(1)).unwrap();
+        tree.add_element_at(0, &element(7)).unwrap();
+        tree.add_batch(vec![(convert_to_bits(HEIGHT, 200).unwrap(), element(2))])
+            .unwrap();
+        tree.prune();
+        let latest = tree.top;
+
+        tree.rollback_to(checkpoint).unwrap();
+        assert_eq!(tree.top, checkpoint);
+        assert_eq!(tree.next_index(), 1);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(
+            tree.get_element(&bits_index).unwrap().value,
+            element(0).value
+        );
+        assert_eq!(
+            tree.prove(&bits_index).unwrap().sibling_hashes,
+            proof.sibling_hashes
+        );
+        assert_eq!(tree.rollback_to(latest), Err(MerkleError::UnknownRoot));
+
+        assert_eq!(tree.append(&element(1)).unwrap(), 1);
+        let mut rebuilt = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        rebuilt.append(&element(0)).unwrap();
+        rebuilt.append(&element(1)).unwrap();
+        assert_eq!(tree.top, rebuilt.top);
+    }
+
+    #[test]
+    fn validate_get_returns_the_matching_root() {
+        const HEIGHT: usize = 6;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let element = Element {
+            value: vec![Fp::from(4)],
+        };
+        tree.add_element_at(9, &element).unwrap();
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+
+        assert_eq!(
+            proof.validate_get(bits_index.clone(), &element, tree.top),
+            Some(tree.top)
+        );
+        let stale = *tree.default_hash_at_level(HEIGHT);
+        assert_eq!(
+            proof.validate_get(bits_index.clone(), &element, stale),
+            None
+        );
+        assert_eq!(
+            proof.validate_get(bits_index[1..].to_vec(), &element, tree.top),
+            None
+        );
+    }
+
+    #[test]
+    fn root_newtype_formats_as_hex() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        tree.add_element_at(2, &Element::new(vec![Fp::from(8)]).unwrap())
+            .unwrap();
+        let root = tree.root();
+        assert_eq!(root.as_bytes(), &tree.top);
+        assert_eq!(root.to_string(), hex::encode(tree.top));
+        assert_eq!(format!("{root:x}"), hex::encode(tree.top));
+        assert_eq!(format!("{root:#x}"), format!("0x{}", hex::encode(tree.top)));
+
+        let proof = tree.prove_at(2).unwrap();
+        let element = Element::new(vec![Fp::from(8)]).unwrap();
+        assert!(proof.validate_at(2, &element, root));
+        assert!(proof.validate_at(2, &element, tree.top));
+        assert!(tree.is_known_root(root));
+    }
+
+    #[test]
+    fn iter_yields_occupied_leaves_in_index_order() {
+        const HEIGHT: usize = 10;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in [900, 3, 512, 0, 77] {
+            tree.add_element_at(index, &Element::new(vec![Fp::from(index + 1)]).unwrap())
+                .unwrap();
+        }
+        tree.remove_element(convert_to_bits(HEIGHT, 77).unwrap())
+            .unwrap();
+        let listed: Vec<(u64, Fp)> = tree.iter().map(|(i, e)| (i, e.value[0])).collect();
+        let expected: Vec<(u64, Fp)> = [0, 3, 512, 900]
+            .into_iter()
+            .map(|i| (i, Fp::from(i + 1)))
+            .collect();
+        assert_eq!(listed, expected);
+
+        for index in [0, 1, 5, u64::MAX] {
+            assert_eq!(
+                convert_from_bits(&convert_to_bits(70, index).unwrap()),
+                Some(index)
+            );
+        }
+        let mut tall = vec![false; 70];
+        tall[5] = true;
+        assert_eq!(convert_from_bits(&tall), None);
+        assert_eq!(convert_from_bits(&[]), Some(0));
+    }
+
+    #[test]
+    fn field_bits_are_the_reversed_path() {
+        for (index, height) in [(0u64, 0), (6, 3), (1, 8), (u64::MAX, 64), (5, 70)] {
+            let mut path = convert_to_bits(height, index).unwrap();
+            path.reverse();
+            assert_eq!(index_to_field_bits::<Fp>(index, height).unwrap(), path);
+        }
+        assert_eq!(
+            index_to_field_bits::<Fp>(6, 3).unwrap(),
+            vec![false, true, true]
+        );
+        assert!(index_to_field_bits::<Fp>(8, 3).is_err());
+    }
+
+    #[test]
+    fn diff_lists_changed_positions() {
+        const HEIGHT: usize = 32;
+        let mut a = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in [1, 50, 1 << 31] {
+            a.add_element_at(index, &Element::new(vec![Fp::from(index)]).unwrap())
+                .unwrap();
+        }
+        let mut b = a.clone();
+        assert!(a.diff(&b).unwrap().is_empty());
+
+        b.add_element_at(7, &Element::new(vec![Fp::from(7)]).unwrap())
+            .unwrap();
+        b.add_element_at(50, &Element::new(vec![Fp::from(51)]).unwrap())
+            .unwrap();
+        b.remove_element(convert_to_bits(HEIGHT, 1 << 31).unwrap())
+            .unwrap();
+        assert_eq!(a.diff(&b).unwrap(), vec![7, 50, 1 << 31]);
+        assert_eq!(b.diff(&a).unwrap(), vec![7, 50, 1 << 31]);
+
+        let shorter = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT - 1);
+        assert!(matches!(
+            a.diff(&shorter),
+            Err(MerkleError::LengthMismatch { .. })
+        ));
+        let other_empty =
+            MerkleTree::<Fp>::initialize(Element::new(vec![Fp::ONE]).unwrap(), HEIGHT);
+        assert_eq!(a.diff(&other_empty), Err(MerkleError::EmptyValueMismatch));
+    }
+
+    #[test]
+    fn verify_in_reuses_the_scratch_buffer() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 8);
+        let elements: Vec<Element<Fp>> = (1..=20u64)
+            .map(|i| Element::new(vec![Fp::from(i), Fp::from(i * 3)]).unwrap())
+            .collect();
+        for (index, element) in elements.iter().enumerate() {
+            tree.add_element_at(index as u64, element).unwrap();
+        }
+        let mut scratch = Vec::new();
+        for (index, element) in elements.iter().enumerate() {
+            let bits_index = convert_to_bits(8, index as u64).unwrap();
+            let proof = tree.prove(&bits_index).unwrap();
+            assert!(proof.verify_in(&bits_index, element, tree.top, &mut scratch));
+            assert!(!proof.verify_in(&bits_index, &elements[0], [0u8; 32], &mut scratch));
+            assert!(!proof.verify_in(&bits_index[1..], element, tree.top, &mut scratch));
+        }
+        assert_eq!(scratch.len(), 2);
+    }
+
+    #[test]
+    fn custom_defaults_pad_empty_subtrees() {
+        const HEIGHT: usize = 6;
+        let empty_element = Element::<Fp>::default();
+        let computed = default_hashes(&Sha256Hasher, &empty_element, HEIGHT);
+        let tree =
+            MerkleTree::<Fp>::initialize_with_defaults(empty_element.clone(), HEIGHT, computed)
+                .unwrap();
+        assert_eq!(
+            tree.top,
+            MerkleTree::<Fp>::initialize(empty_element.clone(), HEIGHT).top
+        );
+
+        let mut padding: Vec<Digest> = (0..=HEIGHT as u8).map(|level| [level; 32]).collect();
+        padding[0] = empty_element.compute_hash();
+        let mut tree =
+            MerkleTree::<Fp>::initialize_with_defaults(empty_element.clone(), HEIGHT, padding)
+                .unwrap();
+        assert_eq!(tree.top, [HEIGHT as u8; 32]);
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        let element = Element::new(vec![Fp::from(9)]).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert_eq!(proof.sibling_hashes[0], [HEIGHT as u8 - 1; 32]);
+        assert!(proof.validate(bits_index.clone(), &element, tree.top));
+        let other = convert_to_bits(HEIGHT, 40).unwrap();
+        tree.add_element(other.clone(), &element).unwrap();
+        tree.remove_element(bits_index).unwrap();
+        let top = tree.top;
+        assert_eq!(tree.rebuild_from_leaves(), top);
+        tree.remove_element(other).unwrap();
+        assert_eq!(tree.top, [HEIGHT as u8; 32]);
+        assert_eq!(tree.rebuild_from_leaves(), [HEIGHT as u8; 32]);
+
+        assert_eq!(
+            MerkleTree::<Fp>::initialize_with_defaults(empty_element.clone(), HEIGHT, vec![]).err(),
+            Some(MerkleError::LengthMismatch {
+                expected: HEIGHT + 1,
+                got: 0
+            })
+        );
+        let wrong_leaf = vec![[0xaa; 32]; HEIGHT + 1];
+        assert_eq!(
+            MerkleTree::<Fp>::initialize_with_defaults(empty_element, HEIGHT, wrong_leaf).err(),
+            Some(MerkleError::EmptyValueMismatch)
+        );
+    }
+
+    #[test]
+    fn equality_compares_roots() {
+        let mut a = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        let mut b = a.clone();
+        let element = Element::new(vec![Fp::from(3)]).unwrap();
+        a.add_element_at(3, &element).unwrap();
+        assert_ne!(a, b);
+        b.add_element_at(5, &element).unwrap();
+        b.remove_element(convert_to_bits(4, 5).unwrap()).unwrap();
+        b.add_element_at(3, &element).unwrap();
+        assert_eq!(a, b);
+        assert!(a.deep_eq(&b));
+
+        let mut shorter = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        shorter.top = a.top;
+        assert_eq!(a, shorter);
+        assert!(!a.deep_eq(&shorter));
+    }
+
+    #[test]
+    fn proofs_dedup_in_sets() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        tree.add_element_at(0, &Element::new(vec![Fp::ONE]).unwrap())
+            .unwrap();
+        let proofs: std::collections::HashSet<Proof> = [0, 8, 8, 0]
+            .into_iter()
+            .map(|index| tree.prove_at(index).unwrap())
+            .collect();
+        assert_eq!(proofs.len(), 2);
+    }
+
+    #[test]
+    fn sibling_order_on_a_fixed_tree() {
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp>::initialize(empty_element.clone(), 3);
+        let elements: Vec<Element<Fp>> = (1..=3u64)
+            .map(|i| Element::new(vec![Fp::from(i)]).unwrap())
+            .collect();
+        for (index, element) in [0, 4, 5].into_iter().zip(&elements) {
+            tree.add_element_at(index, element).unwrap();
+        }
+
+        let hasher = Sha256Hasher;
+        let node = |left: &Digest, right: &Digest| Hasher::<Fp>::hash_nodes(&hasher, left, right);
+        let d = default_hashes(&hasher, &empty_element, 3);
+        let [leaf0, leaf4, leaf5] = [0, 1, 2].map(|i| elements[i].compute_hash());
+        let node_0 = node(&node(&leaf0, &d[0]), &d[1]);
+        let node_10 = node(&leaf4, &leaf5);
+        let node_1 = node(&node_10, &d[1]);
+        assert_eq!(tree.top, node(&node_0, &node_1));
+
+        // Index 5 is bits [1, 0, 1]: root's left child, then the empty `11`, then leaf 4.
+        let proof = tree.prove_at(5).unwrap();
+        assert_eq!(proof.sibling_hashes, vec![node_0, d[1], leaf4]);
+        let proof = tree.prove_at(0).unwrap();
+        assert_eq!(proof.sibling_hashes, vec![node_1, d[1], d[0]]);
+        assert_eq!(
+            tree.get_path_hashes(&[true, false, true]).unwrap(),
+            vec![node_1, node_10, leaf5]
+        );
+        assert_eq!(
+            tree.root().to_string(),
+            "af7aa7990b08eab2de97c8800f3eb168e9cff615e1b30292180b87ba79149487"
+        );
+    }
+
+    #[test]
+    fn height_256_trees_store_only_written_paths() {
+        const HEIGHT: usize = 256;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        assert!(tree.data_store.is_empty());
+        let mut indices = Vec::new();
+        for i in 0..4u64 {
+            let mut bits_index = convert_to_bits(HEIGHT, i * 7919).unwrap();
+            bits_index[i as usize] = true;
+            let element = Element::new(vec![Fp::from(i + 1)]).unwrap();
+            tree.add_element(bits_index.clone(), &element).unwrap();
+            indices.push((bits_index, element));
+        }
+        tree.prune();
+        assert!(tree.data_store.len() <= 4 * HEIGHT);
+        for (bits_index, element) in indices {
+            let proof = tree.prove(&bits_index).unwrap();
+            assert!(proof.validate(bits_index, &element, tree.top));
+        }
+        let absent = vec![false; HEIGHT];
+        let proof = tree.prove_non_membership(&absent).unwrap();
+        assert!(proof.validate_non_membership(absent, &Element::<Fp>::default(), tree.top));
+    }
+
+    #[test]
+    fn validate_hash_skips_the_element() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 6);
+        let element = Element::new(vec![Fp::from(42), Fp::from(7)]).unwrap();
+        let bits_index = convert_to_bits(6, 17).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        let leaf_hash = element.compute_hash();
+        assert!(proof.validate_hash::<Fp>(&bits_index, &leaf_hash, tree.top));
+        assert!(!proof.validate_hash::<Fp>(&bits_index, &leaf_hash[..31], tree.top));
+        assert!(!proof.validate_hash::<Fp>(&bits_index, &[0; 32], tree.top));
+        assert!(!proof.validate_hash::<Fp>(&bits_index[1..], &leaf_hash, tree.top));
+    }
+
+    #[test]
+    fn subtree_roots_commit_to_ranges() {
+        const HEIGHT: usize = 8;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in 0..4 {
+            tree.add_element_at(index, &Element::new(vec![Fp::from(index + 1)]).unwrap())
+                .unwrap();
+        }
+        assert_eq!(tree.subtree_root(&[]).unwrap(), tree.top);
+        assert_eq!(
+            tree.subtree_root(&[true]).unwrap(),
+            *tree.default_hash_at_level(HEIGHT - 1)
+        );
+
+        // Leaves 0..4 sit under the prefix of six zero bits.
+        let mut range = MerkleTree::<Fp>::initialize(Element::default(), 2);
+        for index in 0..4 {
+            range
+                .add_element_at(index, &Element::new(vec![Fp::from(index + 1)]).unwrap())
+                .unwrap();
+        }
+        assert_eq!(tree.subtree_root(&[false; 6]).unwrap(), range.top);
+        let leaf = tree
+            .subtree_root(&convert_to_bits(HEIGHT, 2).unwrap())
+            .unwrap();
+        assert_eq!(
+            leaf,
+            Element::new(vec![Fp::from(3)]).unwrap().compute_hash()
+        );
+        assert!(matches!(
+            tree.subtree_root(&[false; HEIGHT + 1]),
+            Err(MerkleError::LengthMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn proofs_from_other_heights_are_rejected() {
+        const HEIGHT: usize = 6;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let element = Element::new(vec![Fp::from(5)]).unwrap();
+        let bits_index = convert_to_bits(HEIGHT, 5).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert_eq!(proof.expected_height(), HEIGHT);
+
+        let truncated: Proof = Proof::new(proof.sibling_hashes[..HEIGHT - 2].to_vec());
+        assert_eq!(truncated.expected_height(), HEIGHT - 2);
+        assert!(!truncated.validate(bits_index.clone(), &element, tree.top));
+        assert!(!proof.validate(bits_index[2..].to_vec(), &element, tree.top));
+
+        // An interior node folds to the real root along the shorter path; only the height check
+        // tells the two apart.
+        let interior = tree.subtree_root(&bits_index[..HEIGHT - 2]).unwrap();
+        assert!(truncated.validate_hash::<Fp>(&bits_index[..HEIGHT - 2], &interior, tree.top));
+        assert_ne!(truncated.expected_height(), tree.height());
+    }
+
+    #[test]
+    fn verify_owned_converts_the_index() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        let element = Element::single(Fp::from(8));
+        tree.add_element_at(11, &element).unwrap();
+        let proof = tree.prove_at(11).unwrap();
+        let root = tree.top.to_vec();
+        assert!(verify_owned(
+            proof.clone(),
+            11,
+            element.clone(),
+            root.clone(),
+            4
+        ));
+        assert!(!verify_owned(
+            proof.clone(),
+            10,
+            element.clone(),
+            root.clone(),
+            4
+        ));
+        assert!(!verify_owned(
+            proof.clone(),
+            16,
+            element.clone(),
+            root.clone(),
+            4
+        ));
+        assert!(!verify_owned(proof, 11, element, root, 5));
+    }
+
+    #[test]
+    fn chains_link_child_roots_into_parent_leaves() {
+        let mut inner = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let leaf = Element::single(Fp::from(12));
+        inner.add_element_at(5, &leaf).unwrap();
+        let mut outer = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        let wrapped = Element::from_bytes(&inner.top);
+        outer.add_element_at(9, &wrapped).unwrap();
+
+        let inner_bits = convert_to_bits(3, 5).unwrap();
+        let outer_bits = convert_to_bits(4, 9).unwrap();
+        let chain = vec![
+            (
+                inner_bits.clone(),
+                leaf.clone(),
+                inner.prove(&inner_bits).unwrap(),
+            ),
+            (
+                outer_bits.clone(),
+                wrapped,
+                outer.prove(&outer_bits).unwrap(),
+            ),
+        ];
+        assert!(verify_chain(&chain, outer.top));
+        assert!(!verify_chain(&chain, inner.top));
+        assert!(!verify_chain::<Fp, Sha256Hasher, 32>(&[], outer.top));
+
+        let mut broken = chain.clone();
+        broken[0].1 = Element::single(Fp::from(13));
+        assert!(!verify_chain(&broken, outer.top));
+        let mut broken = chain;
+        broken[1].1 = Element::from_bytes(&outer.top);
+        assert!(!verify_chain(&broken, outer.top));
+    }
+
+    #[test]
+    fn siblings_are_listed_from_the_leaf_up() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        tree.add_element_at(1, &Element::single(Fp::from(6)))
+            .unwrap();
+        let proof = tree.prove_at(0).unwrap();
+        let levels: Vec<_> = proof.siblings_with_levels().collect();
+        assert_eq!(levels.len(), 3);
+        let leaf_sibling = tree.leaf_hash(&Element::single(Fp::from(6)));
+        assert_eq!(levels[0], (0, &leaf_sibling));
+        for (level, sibling) in &levels[1..] {
+            assert_eq!(*sibling, tree.default_hash_at_level(*level));
+        }
+    }
+
+    #[test]
+    fn sibling_slices_must_be_digest_width() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let element = Element::single(Fp::from(2));
+        tree.add_element_at(4, &element).unwrap();
+        let proof = tree.prove_at(4).unwrap();
+        let mut siblings: Vec<Vec<u8>> = proof.sibling_hashes.iter().map(|s| s.to_vec()).collect();
+        let decoded = Proof::<Sha256Hasher>::from_sibling_slices(&siblings).unwrap();
+        assert_eq!(decoded, proof);
+
+        siblings[1].truncate(5);
+        assert_eq!(
+            Proof::<Sha256Hasher>::from_sibling_slices(&siblings),
+            Err(MerkleError::LengthMismatch {
+                expected: 32,
+                got: 5
+            })
+        );
+        siblings[1] = vec![0; 33];
+        assert!(Proof::<Sha256Hasher>::from_sibling_slices(&siblings).is_err());
+    }
+
+    #[test]
+    fn windowed_proofs_chain_through_the_sub_root() {
+        const HEIGHT: usize = 6;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for index in [0u64, 3, 9, 40] {
+            tree.add_element_at(index, &Element::single(Fp::from(index + 1)))
+                .unwrap();
+        }
+        let element = Element::single(Fp::from(10));
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        for window_height in 0..=HEIGHT {
+            let (proof, sub_root) = tree.prove_windowed(9, window_height).unwrap();
+            let (prefix, window) = bits_index.split_at(HEIGHT - window_height);
+            assert_eq!(proof.expected_height(), window_height);
+            assert!(proof.validate(window.to_vec(), &element, sub_root));
+            assert!(!proof.validate(window.to_vec(), &Element::<Fp>::default(), sub_root));
+
+            let upper = tree.prove_subtree_root(prefix).unwrap();
+            assert!(upper.validate_hash::<Fp>(prefix, &sub_root, tree.top));
+        }
+        assert!(tree.prove_windowed(9, HEIGHT + 1).is_err());
+        assert!(tree.prove_windowed(1 << HEIGHT, 2).is_err());
+    }
+
+    #[test]
+    fn any_listed_root_is_accepted() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let element = Element::single(Fp::from(1));
+        let bits_index = convert_to_bits(3, 2).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        let old_root = tree.top.to_vec();
+        tree.add_element_at(5, &element).unwrap();
+        let roots = vec![tree.top.to_vec(), old_root, vec![0; 32]];
+        assert!(proof.validate_any(bits_index.clone(), &element, &roots));
+        assert!(!proof.validate_any(bits_index.clone(), &element, &roots[..1]));
+        assert!(!proof.validate_any::<Fp, Vec<u8>>(bits_index.clone(), &element, &[]));
+        assert!(!proof.validate_any(bits_index, &Element::single(Fp::from(2)), &roots));
+    }
+
+    #[test]
+    fn reads_come_with_their_proof() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        tree.add_element_at(6, &Element::single(Fp::from(60)))
+            .unwrap();
+        let (element, proof) = tree.get_and_prove(6).unwrap().unwrap();
+        assert_eq!(element, Element::single(Fp::from(60)));
+        assert!(proof.validate(convert_to_bits(4, 6).unwrap(), &element, tree.top));
+        assert!(tree.get_and_prove(7).unwrap().is_none());
+        assert!(tree.get_and_prove(16).is_err());
+    }
+
+    #[test]
+    fn stats_describe_the_tree() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 10);
+        tree.add_element_at(3, &Element::single(Fp::from(1)))
+            .unwrap();
+        tree.add_element_at(700, &Element::single(Fp::from(2)))
+            .unwrap();
+        let stats = tree.stats();
+        assert_eq!(
+            stats,
+            TreeStats {
+                occupied: 2,
+                nodes_stored: tree.data_store.len(),
+                height: 10,
+                est_proof_bytes: 320,
+            }
+        );
+        assert_eq!(
+            tree.prove_at(3).unwrap().to_flat_bytes().len(),
+            stats.est_proof_bytes
+        );
+        assert_eq!(proof_size_bytes(0, 32), 0);
+    }
+
+    #[test]
+    fn roots_fold_with_a_caller_combine() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let element = Element::single(Fp::from(5));
+        let bits_index = convert_to_bits(3, 6).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        let leaf_hash = element.compute_hash();
+        let sha256 = |left: &Digest, right: &Digest| {
+            <Sha256Hasher as Hasher<Fp>>::hash_nodes(&Sha256Hasher, left, right)
+        };
+        assert_eq!(
+            proof.calculate_root_with(&bits_index, leaf_hash, sha256),
+            Ok(tree.top)
+        );
+
+        let xor = |left: &Digest, right: &Digest| core::array::from_fn(|i| left[i] ^ right[i]);
+        let expected = proof
+            .sibling_hashes
+            .iter()
+            .fold(leaf_hash, |acc, s| xor(&acc, s));
+        assert_eq!(
+            proof.calculate_root_with(&bits_index, leaf_hash, xor),
+            Ok(expected)
+        );
+        assert!(proof
+            .calculate_root_with(&bits_index[1..], leaf_hash, xor)
+            .is_err());
+    }
+
+    #[test]
+    fn merging_disjoint_trees_matches_one_tree() {
+        const HEIGHT: usize = 10;
+        let element = |i: u64| Element::single(Fp::from(i + 1));
+        let mut whole = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut evens = whole.clone();
+        let mut odds = whole.clone();
+        for i in 0..40 {
+            let index = (i * 97 + 13) % (1 << HEIGHT);
+            whole.add_element_at(index, &element(i)).unwrap();
+            let part = if i % 2 == 0 { &mut evens } else { &mut odds };
+            part.add_element_at(index, &element(i)).unwrap();
+        }
+        let mut merged = evens.clone();
+        merged.merge(odds.clone()).unwrap();
+        assert_eq!(merged.top, whole.top);
+        assert!(merged.deep_eq(&whole));
+        let proof = merged.prove_at(13).unwrap();
+        assert!(proof.validate(convert_to_bits(HEIGHT, 13).unwrap(), &element(0), whole.top));
+
+        let top = evens.top;
+        assert_eq!(evens.merge(whole.clone()), Err(MerkleError::LeafOccupied));
+        assert_eq!(evens.top, top);
+        let other_empty = MerkleTree::<Fp>::initialize(Element::single(Fp::ONE), HEIGHT);
+        assert_eq!(
+            evens.merge(other_empty),
+            Err(MerkleError::EmptyValueMismatch)
+        );
+        let shorter = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT - 1);
+        assert!(evens.merge(shorter).is_err());
+    }
+
+    // Hashes its fields directly rather than as an `Element`.
+    struct Transfer {
+        from: u64,
+        amount: u64,
+    }
+
+    impl Leaf<Fp> for Transfer {
+        fn hash_with<H: Hasher<Fp, N>, const N: usize>(&self, hasher: &H) -> Digest<N> {
+            hasher.hash_leaf(&[
+                Fp::from(self.from).to_repr(),
+                Fp::from(self.amount).to_repr(),
+            ])
+        }
+    }
+
+    #[test]
+    fn custom_leaves_are_stored_by_hash() {
+        const HEIGHT: usize = 6;
+        let transfer = |from| Transfer { from, amount: 10 };
+        let mut tree = MerkleTree::<Fp>::initialize_with_history(Element::default(), HEIGHT, 8);
+        let element = Element::single(Fp::from(3));
+        tree.add_element_at(1, &element).unwrap();
+        let before = tree.top;
+        for from in [4, 9, 40] {
+            tree.add_leaf(convert_to_bits(HEIGHT, from).unwrap(), &transfer(from))
+                .unwrap();
+        }
+        let bits_index = convert_to_bits(HEIGHT, 9).unwrap();
+        assert!(tree.contains(&bits_index));
+        assert_eq!(tree.get_element(&bits_index), None);
+        assert_eq!(tree.len(), 4);
+        let proof = tree.prove(&bits_index).unwrap();
+        assert!(proof.validate_leaf::<Fp, _>(&bits_index, &transfer(9), tree.top));
+        assert!(!proof.validate_leaf::<Fp, _>(&bits_index, &transfer(8), tree.top));
+
+        // An element written as a leaf commits like `add_element`.
+        let mut as_leaf = tree.clone();
+        as_leaf
+            .add_leaf(convert_to_bits(HEIGHT, 2).unwrap(), &element)
+            .unwrap();
+        tree.add_element_at(2, &element).unwrap();
+        assert_eq!(as_leaf.top, tree.top);
+
+        let restored = MerkleTree::<Fp>::deserialize(&tree.serialize(), HEIGHT).unwrap();
+        assert!(restored.deep_eq(&tree));
+        let top = tree.top;
+        assert_eq!(tree.clone().rebuild_from_leaves(), top);
+        let mut capped = MerkleTree::<Fp>::builder()
+            .height(HEIGHT)
+            .max_nodes(HEIGHT + 1)
+            .build()
+            .unwrap();
+        capped.merge(tree.clone()).unwrap();
+        assert_eq!(capped.top, top);
+        assert_eq!(capped.prove(&bits_index), tree.prove(&bits_index));
+
+        // Overwriting with an element, then rolling back, brings the hash leaf back.
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        assert_eq!(tree.get_element(&bits_index), Some(element));
+        tree.rollback_to(top).unwrap();
+        assert_eq!(tree.get_element(&bits_index), None);
+        assert!(tree.contains(&bits_index));
+        tree.rollback_to(before).unwrap();
+        assert!(!tree.contains(&bits_index));
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn batch_verify_matches_single_proofs() {
+        const HEIGHT: usize = 10;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let indices = [0u64, 1, 2, 3, 500, 501, 1023];
+        for &index in &indices {
+            tree.add_element_at(index, &Element::new(vec![Fp::from(index + 1)]).unwrap())
+                .unwrap();
+        }
+        let mut items: Vec<(Vec<bool>, Element<Fp>, Proof)> = indices
+            .iter()
+            .map(|&index| {
+                let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+                let element = tree.get_element(&bits_index).unwrap();
+                let proof = tree.prove(&bits_index).unwrap();
+                (bits_index, element, proof)
+            })
+            .collect();
+        assert!(batch_verify(tree.top, &items));
+        assert!(batch_verify(tree.top, &items[..0]));
+        assert!(!batch_verify([0u8; 32], &items));
+
+        // A bad leaf after a good neighbour is caught at their shared ancestor.
+        items[1].1 = Element::new(vec![Fp::from(99)]).unwrap();
+        assert!(!batch_verify(tree.top, &items));
+        items[1].1 = tree.get_element(&items[1].0).unwrap();
+        items[5].2.sibling_hashes[HEIGHT - 1] = [0; 32];
+        assert!(!batch_verify(tree.top, &items));
+        for item in &items {
+            let (bits_index, element, proof) = item;
+            let single = proof.validate(bits_index.clone(), element, tree.top);
+            assert_eq!(single, batch_verify(tree.top, core::slice::from_ref(item)));
+        }
+    }
+
+    #[test]
+    fn capacity_at_the_u64_boundary() {
+        let element = Element::new(vec![Fp::ONE]).unwrap();
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 0);
+        assert_eq!(tree.capacity(), Some(1));
+        assert_eq!(tree.append(&element), Ok(0));
+        assert_eq!(tree.append(&element), Err(MerkleError::TreeFull));
+
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 63);
+        assert_eq!(tree.capacity(), Some(1 << 63));
+        tree.add_element_at((1 << 63) - 1, &element).unwrap();
+        assert!(tree.add_element_at(1 << 63, &element).is_err());
+
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 64);
+        assert_eq!(tree.capacity(), None);
+        tree.add_element_at(u64::MAX, &element).unwrap();
+        assert_eq!(tree.iter().next().map(|(index, _)| index), Some(u64::MAX));
+        assert!(tree
+            .prove_at(u64::MAX)
+            .unwrap()
+            .validate_at(u64::MAX, &element, tree.top));
+    }
+
+    #[test]
+    fn update_with_proof_matches_add_element() {
+        const HEIGHT: usize = 8;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut expected = tree.clone();
+        let bits_index = convert_to_bits(HEIGHT, 77).unwrap();
+        for i in 1..=3u64 {
+            let element = Element::new(vec![Fp::from(i)]).unwrap();
+            let proof = tree.prove(&bits_index).unwrap();
+            tree.update_with_proof(bits_index.clone(), &element, &proof)
+                .unwrap();
+            expected.add_element(bits_index.clone(), &element).unwrap();
+            assert!(tree.deep_eq(&expected));
+        }
+
+        let stale = tree.prove(&bits_index).unwrap();
+        tree.add_element_at(3, &Element::new(vec![Fp::from(9)]).unwrap())
+            .unwrap();
+        let element = Element::new(vec![Fp::from(4)]).unwrap();
+        let top = tree.top;
+        assert_eq!(
+            tree.update_with_proof(bits_index.clone(), &element, &stale),
+            Err(MerkleError::StaleProof)
+        );
+        let other = tree.prove_at(76).unwrap();
+        assert_eq!(
+            tree.update_with_proof(bits_index, &element, &other),
+            Err(MerkleError::StaleProof)
+        );
+        assert_eq!(tree.top, top);
+    }
+
+    #[test]
+    fn directed_proofs_carry_their_index() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 5);
+        let element = Element::new(vec![Fp::from(6)]).unwrap();
+        let bits_index = convert_to_bits(5, 6).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let siblings = tree.get_sibling_hashes(&bits_index).unwrap();
+
+        let directed: Proof = Proof::with_directions(siblings.clone(), bits_index.clone());
+        assert!(directed.validate_directed(&element, tree.top));
+        assert!(directed.validate(bits_index.clone(), &element, tree.top));
+        let mut other = bits_index.clone();
+        other[4] = !other[4];
+        assert_eq!(
+            directed.calculate_root(other, &element),
+            Err(MerkleError::DirectionMismatch)
+        );
+        assert!(!Proof::<Sha256Hasher>::new(siblings).validate_directed(&element, tree.top));
+    }
+
+    #[test]
+    fn insert_returns_the_replaced_element() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        let bits_index = convert_to_bits(4, 9).unwrap();
+        let first = Element::new(vec![Fp::from(1)]).unwrap();
+        let second = Element::new(vec![Fp::from(2)]).unwrap();
+        assert_eq!(tree.insert(bits_index.clone(), &first), Ok(None));
+        assert_eq!(tree.insert(bits_index.clone(), &second), Ok(Some(first)));
+        assert_eq!(
+            tree.insert(bits_index, &Element::default()),
+            Ok(Some(second))
+        );
+        assert!(tree.is_empty());
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    fn check_leaves_poseidon() {
+        use crate::hasher::PoseidonHasher;
+
+        const HEIGHT: usize = 8;
+        let empty_element = Element::<Fp>::default();
+        let mut tree = MerkleTree::<Fp, PoseidonHasher<Fp>>::initialize(empty_element, HEIGHT);
+
+        for index in 0..10 {
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            let element = Element {
+                value: vec![Fp::random(&mut rand::thread_rng()), Fp::from(index)],
             };
-            assert!(new_proof.validate(bits_index, &element, &tree.top));
+            tree.add_element(bits_index.clone(), &element).unwrap();
+            let proof = tree.prove(&bits_index).unwrap();
+            assert_eq!(tree.top.len(), 32);
+            assert!(proof.validate(bits_index, &element, tree.top));
         }
     }
 }
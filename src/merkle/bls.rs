@@ -0,0 +1,86 @@
+// The tree against a second curve's scalar field, so nothing in hashing, snapshots or byte
+// packing can quietly depend on pasta `Fp`. `Scalar`'s repr is 32 bytes like `Fp`'s, so the
+// byte-level round trips below check that encodings go through the field, not its width.
+use super::{convert_to_bits, field_from_repr_bytes, sample_tree, Element, MerkleTree};
+use crate::error::MerkleError;
+use crate::hasher::Sha256Hasher;
+use alloc::vec;
+use alloc::vec::Vec;
+use bls12_381::Scalar;
+use ff::{Field, PrimeField};
+
+const HEIGHT: usize = 10;
+const SAMPLED: u64 = 20;
+// Past the sampled leaves, holding a two-value element.
+const WIDE: u64 = 1000;
+
+fn bls_tree() -> MerkleTree<Scalar> {
+    let mut tree = sample_tree::<Scalar>(HEIGHT, SAMPLED as usize, 3);
+    let element = Element::new(vec![Scalar::from(WIDE), -Scalar::ONE]).unwrap();
+    tree.add_element_at(WIDE, &element).unwrap();
+    tree
+}
+
+fn occupied() -> impl Iterator<Item = u64> {
+    (0..SAMPLED).chain([WIDE])
+}
+
+#[test]
+fn bls_scalar_trees_prove_and_verify() {
+    let tree = bls_tree();
+    for i in occupied() {
+        let bits_index = convert_to_bits(HEIGHT, i).unwrap();
+        let element = tree.get_element(&bits_index).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+        assert!(proof.validate(bits_index.clone(), &element, tree.top));
+        assert!(!proof.validate(bits_index.clone(), &Element::<Scalar>::default(), tree.top));
+
+        let compact = tree.prove_compact(&bits_index).unwrap();
+        let defaults = super::default_hashes(&Sha256Hasher, &Element::<Scalar>::default(), HEIGHT);
+        assert!(compact.validate(bits_index, &element, tree.top, &defaults));
+    }
+    let empty = convert_to_bits(HEIGHT, SAMPLED).unwrap();
+    let proof = tree.prove(&empty).unwrap();
+    assert!(proof.validate(empty, &Element::<Scalar>::default(), tree.top));
+}
+
+#[test]
+fn bls_scalar_encodings_round_trip() {
+    let tree = bls_tree();
+    let restored = MerkleTree::<Scalar>::deserialize(&tree.serialize(), HEIGHT).unwrap();
+    assert!(restored.deep_eq(&tree));
+
+    let bytes: Vec<u8> = (0..100).collect();
+    assert_eq!(Element::<Scalar>::from_bytes(&bytes).to_bytes(), bytes);
+
+    let value = Scalar::random(&mut rand::thread_rng());
+    let repr = value.to_repr();
+    assert_eq!(field_from_repr_bytes::<Scalar>(repr.as_ref()), Some(value));
+    assert_eq!(field_from_repr_bytes::<Scalar>(&repr.as_ref()[1..]), None);
+    assert_eq!(
+        Element::<Scalar>::from_reprs(&[[0xff; 32]]),
+        Err(MerkleError::NonCanonicalField)
+    );
+}
+
+#[cfg(feature = "borsh")]
+#[test]
+fn bls_scalar_borsh_round_trips() {
+    let tree = bls_tree();
+    for i in occupied() {
+        let bits_index = convert_to_bits(HEIGHT, i).unwrap();
+        let element = tree.get_element(&bits_index).unwrap();
+        let bytes = borsh::to_vec(&element).unwrap();
+        let decoded: Element<Scalar> = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, element);
+
+        let proof = tree.prove(&bits_index).unwrap();
+        let proof: super::Proof<Sha256Hasher> =
+            borsh::from_slice(&borsh::to_vec(&proof).unwrap()).unwrap();
+        assert!(proof.validate(bits_index, &decoded, tree.top));
+    }
+
+    // A canonical `Scalar` repr is out of range for the smaller pasta modulus.
+    let scalar = borsh::to_vec(&Element::new(vec![-Scalar::ONE]).unwrap()).unwrap();
+    assert!(borsh::from_slice::<Element<pasta_curves::Fp>>(&scalar).is_err());
+}
@@ -0,0 +1,193 @@
+use super::{check_element, Element, MerkleTree};
+use crate::error::MerkleError;
+use crate::hasher::{Hasher, Sha256Hasher};
+use alloc::vec::Vec;
+use ff::{PrimeField, PrimeFieldBits};
+
+// Collects tree options so constructors do not grow a positional argument per feature. Only the
+// height is required; the empty value defaults to `Element::default()` and history to off.
+#[derive(Clone, Debug)]
+pub struct MerkleTreeBuilder<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    height: Option<usize>,
+    empty_value: Element<F>,
+    hasher: H,
+    history_capacity: usize,
+    domain: Option<Vec<u8>>,
+    max_nodes: Option<usize>,
+}
+
+impl<F: PrimeField + PrimeFieldBits> MerkleTreeBuilder<F> {
+    pub fn new() -> Self {
+        Self {
+            height: None,
+            empty_value: Element::default(),
+            hasher: Sha256Hasher,
+            history_capacity: 0,
+            domain: None,
+            max_nodes: None,
+        }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits> Default for MerkleTreeBuilder<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTreeBuilder<F, H, N> {
+    pub fn height(mut self, height: usize) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    pub fn empty_value(mut self, empty_value: Element<F>) -> Self {
+        self.empty_value = empty_value;
+        self
+    }
+
+    pub fn hasher<H2: Hasher<F, N2>, const N2: usize>(
+        self,
+        hasher: H2,
+    ) -> MerkleTreeBuilder<F, H2, N2> {
+        MerkleTreeBuilder {
+            height: self.height,
+            empty_value: self.empty_value,
+            hasher,
+            history_capacity: self.history_capacity,
+            domain: self.domain,
+            max_nodes: self.max_nodes,
+        }
+    }
+
+    // See `MerkleTree::initialize_with_history`.
+    pub fn history_capacity(mut self, capacity: usize) -> Self {
+        self.history_capacity = capacity;
+        self
+    }
+
+    // See `MerkleTree::initialize_with_domain`.
+    pub fn domain(mut self, domain: &[u8]) -> Self {
+        self.domain = Some(domain.to_vec());
+        self
+    }
+
+    // Bounds the internal nodes kept in `data_store`. Past it the least recently walked nodes are
+    // dropped and rebuilt from the leaves when a walk needs them, which never changes a root or
+    // proof. Rebuilding a node rehashes every occupied leaf under it, so a miss costs time in the
+    // number of those leaves, up to all of them just below the root. `build` refuses a cap below
+    // `height + 1`, which could not keep even the path a write just walked.
+    pub fn max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    pub fn build(self) -> Result<MerkleTree<F, H, N>, MerkleError> {
+        let height = self.height.ok_or(MerkleError::MissingHeight)?;
+        check_element(&self.empty_value)?;
+        if let Some(max_nodes) = self.max_nodes.filter(|&max_nodes| max_nodes <= height) {
+            return Err(MerkleError::NodeCapTooSmall { max_nodes, height });
+        }
+        let mut tree = match self.domain {
+            Some(domain) => {
+                MerkleTree::initialize_in_domain(self.empty_value, height, &domain, self.hasher)
+            }
+            None => MerkleTree::initialize_with_hasher(self.empty_value, height, self.hasher),
+        };
+        tree.history_capacity = self.history_capacity;
+        tree.max_nodes = self.max_nodes;
+        tree.record_root();
+        Ok(tree)
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits> MerkleTree<F> {
+    pub fn builder() -> MerkleTreeBuilder<F> {
+        MerkleTreeBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Digest;
+    use ff::Field;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn builder_matches_the_constructors() {
+        let tree = MerkleTree::<Fp>::builder().height(12).build().unwrap();
+        assert_eq!(tree, MerkleTree::initialize(Element::default(), 12));
+        assert!(tree.recent_roots().is_empty());
+
+        let empty_value = Element::new(vec![Fp::ONE]).unwrap();
+        let tree = MerkleTree::<Fp>::builder()
+            .history_capacity(4)
+            .empty_value(empty_value.clone())
+            .height(5)
+            .build()
+            .unwrap();
+        let expected = MerkleTree::<Fp>::initialize_with_history(empty_value, 5, 4);
+        assert!(tree.deep_eq(&expected));
+        assert_eq!(tree.recent_roots(), expected.recent_roots());
+    }
+
+    #[test]
+    fn builder_swaps_the_hasher() {
+        #[derive(Clone, Default)]
+        struct XorHasher;
+
+        impl Hasher<Fp, 4> for XorHasher {
+            fn hash_leaf(&self, values: &[[u8; 32]]) -> Digest<4> {
+                let digest = Hasher::<Fp>::hash_leaf(&Sha256Hasher, values);
+                digest[..4].try_into().unwrap()
+            }
+
+            fn hash_nodes(&self, left: &Digest<4>, right: &Digest<4>) -> Digest<4> {
+                core::array::from_fn(|i| left[i].rotate_left(1) ^ right[i])
+            }
+        }
+
+        let tree: MerkleTree<Fp, XorHasher, 4> = MerkleTree::<Fp>::builder()
+            .hasher(XorHasher)
+            .height(3)
+            .build()
+            .unwrap();
+        assert_eq!(tree.root().as_bytes().len(), 4);
+    }
+
+    #[test]
+    fn builder_rejects_incomplete_options() {
+        assert_eq!(
+            MerkleTree::<Fp>::builder().build().err(),
+            Some(MerkleError::MissingHeight)
+        );
+        assert_eq!(
+            MerkleTree::<Fp>::builder()
+                .height(3)
+                .empty_value(Element { value: vec![] })
+                .build()
+                .err(),
+            Some(MerkleError::EmptyElement)
+        );
+        for max_nodes in [0, 3] {
+            assert_eq!(
+                MerkleTree::<Fp>::builder()
+                    .height(3)
+                    .max_nodes(max_nodes)
+                    .build()
+                    .err(),
+                Some(MerkleError::NodeCapTooSmall {
+                    max_nodes,
+                    height: 3
+                })
+            );
+        }
+        let tree = MerkleTree::<Fp>::builder().height(3).max_nodes(4).build();
+        assert_eq!(tree.unwrap().max_nodes(), Some(4));
+    }
+}
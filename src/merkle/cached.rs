@@ -0,0 +1,178 @@
+use super::{check_element, Element, MerkleTree, Proof};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher, Sha256Hasher};
+use alloc::vec::Vec;
+use core::cell::OnceCell;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use ff::{PrimeField, PrimeFieldBits};
+
+// An `Element` that hashes itself at most once, under `H::default()`. The element is only
+// reachable by shared reference, so the cached digest cannot go stale; `into_inner` gives it
+// back for changes. `MerkleTree::add_cached` and `Proof::validate_cached` take the cached digest;
+// through `Deref` the other tree and proof APIs see a plain `&Element` and hash it again.
+#[derive(Clone, Debug)]
+pub struct CachedElement<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    element: Element<F>,
+    hash: OnceCell<Digest<N>>,
+    _marker: PhantomData<H>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> From<Element<F>>
+    for CachedElement<F, H, N>
+{
+    fn from(element: Element<F>) -> Self {
+        Self {
+            element,
+            hash: OnceCell::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> CachedElement<F, H, N> {
+    pub fn compute_hash(&self) -> Digest<N> {
+        *self
+            .hash
+            .get_or_init(|| self.element.compute_hash_with(&H::default()))
+    }
+
+    pub fn into_inner(self) -> Element<F> {
+        self.element
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> Deref
+    for CachedElement<F, H, N>
+{
+    type Target = Element<F>;
+
+    fn deref(&self) -> &Element<F> {
+        &self.element
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    // `add_element` with the leaf hash taken from the cache, so writing the same element again
+    // costs only the path. The cache hashes under `H::default()`, like `Proof::validate_leaf`.
+    pub fn add_cached(
+        &mut self,
+        bits_index: Vec<bool>,
+        element: &CachedElement<F, H, N>,
+    ) -> Result<(), MerkleError> {
+        self.start_op();
+        check_element(element)?;
+        let leaf_hash = self.in_domain(element.compute_hash());
+        self.write_leaf(bits_index.clone(), leaf_hash)?;
+        self.store_leaf(bits_index, element.element.clone(), &leaf_hash);
+        Ok(())
+    }
+}
+
+impl<H, const N: usize> Proof<H, N> {
+    pub fn validate_cached<F: PrimeField + PrimeFieldBits>(
+        &self,
+        bits_index: &[bool],
+        element: &CachedElement<F, H, N>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.validate_hash::<F>(bits_index, &element.compute_hash(), root_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{convert_to_bits, MerkleTree};
+    use pasta_curves::Fp;
+    use std::cell::Cell;
+
+    std::thread_local! {
+        static LEAF_HASHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[derive(Clone, Default)]
+    struct CountingHasher;
+
+    impl Hasher<Fp> for CountingHasher {
+        fn hash_leaf(&self, values: &[[u8; 32]]) -> Digest {
+            LEAF_HASHES.with(|count| count.set(count.get() + 1));
+            Hasher::<Fp>::hash_leaf(&Sha256Hasher, values)
+        }
+
+        fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+            Hasher::<Fp>::hash_nodes(&Sha256Hasher, left, right)
+        }
+    }
+
+    #[test]
+    fn cached_hash_matches_the_element_hash() {
+        let element = Element::new(vec![Fp::from(7), Fp::from(8)]).unwrap();
+        let cached = CachedElement::<Fp>::from(element.clone());
+        assert_eq!(cached.compute_hash(), element.compute_hash());
+        assert_eq!(cached.compute_hash(), element.compute_hash());
+
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 3);
+        let bits_index = convert_to_bits(3, 2).unwrap();
+        tree.add_element(bits_index.clone(), &cached).unwrap();
+        assert_eq!(tree.leaf_hash(&cached), cached.compute_hash());
+        let proof = tree.prove(&bits_index).unwrap();
+        assert!(proof.validate_hash::<Fp>(&bits_index, &cached.compute_hash(), tree.top));
+        assert_eq!(cached.into_inner(), element);
+    }
+
+    #[test]
+    fn cached_writes_and_checks_hash_the_element_once() {
+        const HEIGHT: usize = 6;
+        let element = Element::new(vec![Fp::from(5), Fp::from(6)]).unwrap();
+        let cached = CachedElement::<Fp, CountingHasher>::from(element.clone());
+        let mut tree = MerkleTree::<Fp, CountingHasher>::initialize(Element::default(), HEIGHT);
+        let mut plain = tree.clone();
+
+        LEAF_HASHES.with(|count| count.set(0));
+        for index in 0..8 {
+            let bits_index = convert_to_bits(HEIGHT, index * 5).unwrap();
+            tree.add_cached(bits_index.clone(), &cached).unwrap();
+            let proof = tree.prove(&bits_index).unwrap();
+            assert!(proof.validate_cached(&bits_index, &cached, tree.top));
+            assert!(!proof.validate_cached(&bits_index, &cached, [0u8; 32]));
+        }
+        assert_eq!(LEAF_HASHES.with(Cell::get), 1);
+
+        for index in 0..8 {
+            let bits_index = convert_to_bits(HEIGHT, index * 5).unwrap();
+            plain.add_element(bits_index, &element).unwrap();
+        }
+        assert_eq!(LEAF_HASHES.with(Cell::get), 9);
+        assert_eq!(plain.top, tree.top);
+        assert_eq!(
+            tree.get_element(&convert_to_bits(HEIGHT, 5).unwrap()),
+            Some(element)
+        );
+    }
+
+    #[test]
+    fn cached_writes_respect_the_tree_domain() {
+        let cached = CachedElement::<Fp>::from(Element::new(vec![Fp::from(3)]).unwrap());
+        let mut tree = MerkleTree::<Fp>::initialize_with_domain(Element::default(), 4, b"users");
+        let mut plain = tree.clone();
+        let bits_index = convert_to_bits(4, 3).unwrap();
+        tree.add_cached(bits_index.clone(), &cached).unwrap();
+        plain.add_element(bits_index, &cached).unwrap();
+        assert_eq!(tree.top, plain.top);
+        assert_eq!(
+            tree.add_cached(
+                vec![false; 4],
+                &CachedElement::from(Element { value: vec![] })
+            ),
+            Err(MerkleError::EmptyElement)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use super::{check_element, convert_to_bits, default_hashes, Element, Proof};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher, Sha256Hasher};
+use alloc::vec::Vec;
+use ff::{PrimeField, PrimeFieldBits};
+
+// Nodes addressed by (level, position) in flat vectors rather than by hash, for trees where most
+// leaves get written. `levels[level]` only grows as far as the rightmost written position; nodes
+// past its end are that level's default. Roots and proofs match `MerkleTree` with the same hasher.
+// Only for densely filled, left-packed indices: writing index `i` allocates `i + 1` leaves and
+// about as many nodes, so a sparse tree with far-out indices belongs in `MerkleTree`.
+#[derive(Clone, Debug)]
+pub struct DenseTree<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    pub top: Digest<N>,
+    levels: Vec<Vec<Digest<N>>>,
+    default_hashes: Vec<Digest<N>>,
+    leaves: Vec<Option<Element<F>>>,
+    hasher: H,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> DenseTree<F, H, N> {
+    pub fn initialize(empty_value: Element<F>, height: usize) -> Self {
+        Self::initialize_with_hasher(empty_value, height, H::default())
+    }
+
+    pub fn initialize_with_hasher(empty_value: Element<F>, height: usize, hasher: H) -> Self {
+        let default_hashes = default_hashes(&hasher, &empty_value, height);
+        Self {
+            top: default_hashes[height],
+            levels: (0..=height).map(|_| Vec::new()).collect(),
+            default_hashes,
+            leaves: Vec::new(),
+            hasher,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.default_hashes.len() - 1
+    }
+
+    // Positions past what `usize` can address are refused here rather than bounds-checked later.
+    fn position(&self, index: u64) -> Result<usize, MerkleError> {
+        convert_to_bits(self.height(), index)?;
+        usize::try_from(index).map_err(|_| MerkleError::IndexOutOfRange {
+            index,
+            height: self.height(),
+        })
+    }
+
+    fn node(&self, level: usize, position: usize) -> Digest<N> {
+        self.levels[level]
+            .get(position)
+            .copied()
+            .unwrap_or(self.default_hashes[level])
+    }
+
+    fn set_node(&mut self, level: usize, position: usize, hash: Digest<N>) {
+        let nodes = &mut self.levels[level];
+        if nodes.len() <= position {
+            nodes.resize(position + 1, self.default_hashes[level]);
+        }
+        nodes[position] = hash;
+    }
+
+    pub fn add_element_at(&mut self, index: u64, element: &Element<F>) -> Result<(), MerkleError> {
+        check_element(element)?;
+        let position = self.position(index)?;
+        self.write_leaf(position, element.compute_hash_with(&self.hasher));
+        if self.leaves.len() <= position {
+            self.leaves.resize(position + 1, None);
+        }
+        self.leaves[position] = Some(element.clone());
+        Ok(())
+    }
+
+    // Past the rightmost written leaf every position is already empty, so nothing grows.
+    pub fn remove_element_at(&mut self, index: u64) -> Result<(), MerkleError> {
+        let position = self.position(index)?;
+        if position >= self.leaves.len() {
+            return Ok(());
+        }
+        self.write_leaf(position, self.default_hashes[0]);
+        if let Some(leaf) = self.leaves.get_mut(position) {
+            *leaf = None;
+        }
+        Ok(())
+    }
+
+    pub fn get_element(&self, index: u64) -> Option<Element<F>> {
+        let position = usize::try_from(index).ok()?;
+        self.leaves.get(position)?.clone()
+    }
+
+    fn write_leaf(&mut self, position: usize, leaf_hash: Digest<N>) {
+        self.set_node(0, position, leaf_hash);
+        let mut hash = leaf_hash;
+        for level in 1..=self.height() {
+            let child = shifted(position, level - 1);
+            let (left, right) = if child & 1 == 1 {
+                (self.node(level - 1, child ^ 1), hash)
+            } else {
+                (hash, self.node(level - 1, child ^ 1))
+            };
+            hash = self.hasher.hash_nodes(&left, &right);
+            self.set_node(level, child >> 1, hash);
+        }
+        self.top = hash;
+    }
+
+    // Same sibling order as `MerkleTree::prove`: the root's child first, the leaf's sibling last.
+    pub fn prove_at(&self, index: u64) -> Result<Proof<H, N>, MerkleError> {
+        let position = self.position(index)?;
+        let sibling_hashes = (0..self.height())
+            .rev()
+            .map(|level| self.node(level, shifted(position, level) ^ 1))
+            .collect();
+        Ok(Proof::new(sibling_hashes))
+    }
+}
+
+// `position >> level`, which is 0 once `level` reaches `usize::BITS` in trees taller than that.
+fn shifted(position: usize, level: usize) -> usize {
+    u32::try_from(level)
+        .ok()
+        .and_then(|level| position.checked_shr(level))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::MerkleTree;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn dense_tree_agrees_with_the_sparse_tree() {
+        let mut dense = DenseTree::<Fp>::initialize(Element::default(), 5);
+        let mut sparse = MerkleTree::<Fp>::initialize(Element::default(), 5);
+        assert_eq!(dense.top, sparse.top);
+        for i in [0u64, 31, 7, 8, 7, 20] {
+            let element = Element::new(vec![Fp::from(i + 100)]).unwrap();
+            dense.add_element_at(i, &element).unwrap();
+            sparse.add_element_at(i, &element).unwrap();
+            assert_eq!(dense.top, sparse.top);
+        }
+        dense.remove_element_at(31).unwrap();
+        sparse
+            .remove_element(convert_to_bits(5, 31).unwrap())
+            .unwrap();
+        assert_eq!(dense.top, sparse.top);
+        assert_eq!(dense.get_element(31), None);
+
+        for i in 0..32 {
+            let proof = dense.prove_at(i).unwrap();
+            assert_eq!(proof, sparse.prove_at(i).unwrap());
+            let element = dense.get_element(i).unwrap_or_default();
+            assert!(proof.validate(convert_to_bits(5, i).unwrap(), &element, dense.top));
+        }
+        assert!(dense.prove_at(32).is_err());
+        assert!(dense.add_element_at(32, &Element::default()).is_err());
+    }
+
+    #[test]
+    fn tall_dense_trees_agree_with_the_sparse_tree() {
+        const HEIGHT: usize = 70;
+        let mut dense = DenseTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut sparse = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for i in [0u64, 5, 2] {
+            let element = Element::new(vec![Fp::from(i + 1)]).unwrap();
+            dense.add_element_at(i, &element).unwrap();
+            sparse.add_element_at(i, &element).unwrap();
+        }
+        assert_eq!(dense.top, sparse.top);
+        assert_eq!(dense.prove_at(5).unwrap(), sparse.prove_at(5).unwrap());
+
+        let top = dense.top;
+        dense.remove_element_at(1 << 40).unwrap();
+        assert_eq!(dense.top, top);
+        assert_eq!(dense.leaves.len(), 6);
+        assert!(dense.levels.iter().all(|nodes| nodes.len() <= 6));
+    }
+}
@@ -0,0 +1,44 @@
+use super::{Element, MerkleTree};
+use ff::{PrimeField, PrimeFieldBits};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+impl<F: PrimeField + PrimeFieldBits> Element<F> {
+    // A single uniformly random field value.
+    pub fn sample(rng: &mut impl RngCore) -> Self {
+        Self {
+            value: vec![F::random(rng)],
+        }
+    }
+}
+
+// A tree with `n` sampled elements appended at indices 0..n, drawn from ChaCha8 seeded with
+// `seed`, so the same arguments always give the same root. Panics if `n` leaves do not fit.
+pub fn sample_tree<F: PrimeField + PrimeFieldBits>(
+    height: usize,
+    n: usize,
+    seed: u64,
+) -> MerkleTree<F> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let elements: Vec<Element<F>> = (0..n).map(|_| Element::sample(&mut rng)).collect();
+    MerkleTree::from_elements(Element::default(), height, elements).expect("n leaves fit the tree")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn same_seed_same_root() {
+        let tree = sample_tree::<Fp>(10, 50, 7);
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.top, sample_tree::<Fp>(10, 50, 7).top);
+        assert_ne!(tree.top, sample_tree::<Fp>(10, 50, 8).top);
+        assert_ne!(tree.top, sample_tree::<Fp>(10, 49, 7).top);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let first = Element::<Fp>::sample(&mut rng);
+        assert_eq!(tree.get_element(&[false; 10]).unwrap().value, first.value);
+    }
+}
@@ -0,0 +1,159 @@
+use super::{convert_to_bits, Element, MerkleTree, Proof, Root};
+use crate::error::MerkleError;
+use crate::hasher::{Hasher, Sha256Hasher};
+use alloc::collections::BTreeMap;
+use ff::{PrimeField, PrimeFieldBits};
+
+// One subtree per user under a top tree whose leaf at a user's id is `Element::from_bytes` of
+// that user's subtree root. The top tree's empty value is the wrapped root of an empty subtree,
+// so a user with no tree yet proves exactly like one holding no elements. Subtrees are only
+// reachable by shared reference, which keeps every top leaf in step with its subtree.
+#[derive(Clone, Debug)]
+pub struct ForestTree<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    top: MerkleTree<F, H, N>,
+    trees: BTreeMap<u64, MerkleTree<F, H, N>>,
+    empty_tree: MerkleTree<F, H, N>,
+}
+
+// `inner` proves the element in the user's subtree, `outer` proves that subtree's wrapped root
+// in the top tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForestProof<H = Sha256Hasher, const N: usize = 32> {
+    pub inner: Proof<H, N>,
+    pub outer: Proof<H, N>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> ForestTree<F, H, N> {
+    pub fn initialize(empty_value: Element<F>, top_height: usize, sub_height: usize) -> Self {
+        let empty_tree = MerkleTree::initialize(empty_value, sub_height);
+        let top = MerkleTree::initialize(Element::from_bytes(&empty_tree.top), top_height);
+        Self {
+            top,
+            trees: BTreeMap::new(),
+            empty_tree,
+        }
+    }
+
+    pub fn root(&self) -> Root<N> {
+        self.top.root()
+    }
+
+    pub fn tree(&self, user: u64) -> &MerkleTree<F, H, N> {
+        self.trees.get(&user).unwrap_or(&self.empty_tree)
+    }
+
+    pub fn add_element(
+        &mut self,
+        user: u64,
+        index: u64,
+        element: &Element<F>,
+    ) -> Result<(), MerkleError> {
+        let user_bits = convert_to_bits(self.top.height(), user)?;
+        let tree = self
+            .trees
+            .entry(user)
+            .or_insert_with(|| self.empty_tree.clone());
+        tree.add_element_at(index, element)?;
+        let wrapped = Element::from_bytes(&tree.top);
+        self.top.add_element(user_bits, &wrapped)
+    }
+
+    pub fn remove_element(&mut self, user: u64, index: u64) -> Result<(), MerkleError> {
+        let user_bits = convert_to_bits(self.top.height(), user)?;
+        let Some(tree) = self.trees.get_mut(&user) else {
+            return convert_to_bits(self.empty_tree.height(), index).map(|_| ());
+        };
+        tree.remove_element(convert_to_bits(tree.height(), index)?)?;
+        let wrapped = Element::from_bytes(&tree.top);
+        self.top.add_element(user_bits, &wrapped)
+    }
+
+    pub fn get_element(&self, user: u64, index: u64) -> Option<Element<F>> {
+        let tree = self.trees.get(&user)?;
+        tree.get_element(&convert_to_bits(tree.height(), index).ok()?)
+    }
+
+    pub fn prove_in_forest(&self, user: u64, index: u64) -> Result<ForestProof<H, N>, MerkleError> {
+        let outer = self.top.prove_at(user)?;
+        let inner = self.tree(user).prove_at(index)?;
+        Ok(ForestProof { inner, outer })
+    }
+}
+
+impl<H, const N: usize> ForestProof<H, N> {
+    // The two-link case of `verify_chain`, with the heights taken from the proofs.
+    pub fn validate<F: PrimeField + PrimeFieldBits>(
+        &self,
+        user: u64,
+        index: u64,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let (Ok(user_bits), Ok(bits_index)) = (
+            convert_to_bits(self.outer.expected_height(), user),
+            convert_to_bits(self.inner.expected_height(), index),
+        ) else {
+            return false;
+        };
+        self.inner
+            .calculate_root(bits_index, element)
+            .is_ok_and(|sub_root| {
+                self.outer
+                    .validate(user_bits, &Element::from_bytes(&sub_root), root_hash)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn forest_proofs_track_subtree_updates() {
+        let mut forest = ForestTree::<Fp>::initialize(Element::default(), 4, 3);
+        let empty_root = forest.root();
+        let element = Element::single(Fp::from(9));
+
+        // An untouched user proves emptiness like any other.
+        let proof = forest.prove_in_forest(2, 5).unwrap();
+        assert!(proof.validate(2, 5, &Element::<Fp>::default(), empty_root));
+
+        forest.add_element(2, 5, &element).unwrap();
+        forest.add_element(7, 1, &element).unwrap();
+        assert_ne!(forest.root(), empty_root);
+        assert_eq!(forest.get_element(2, 5), Some(element.clone()));
+        for (user, index) in [(2, 5), (7, 1)] {
+            let proof = forest.prove_in_forest(user, index).unwrap();
+            assert!(proof.validate(user, index, &element, forest.root()));
+            assert!(!proof.validate(user, index, &Element::<Fp>::default(), forest.root()));
+            assert!(!proof.validate(user ^ 1, index, &element, forest.root()));
+        }
+
+        let proof = forest.prove_in_forest(2, 5).unwrap();
+        let chain: Vec<_> = [
+            (convert_to_bits(3, 5).unwrap(), element.clone(), proof.inner),
+            (
+                convert_to_bits(4, 2).unwrap(),
+                Element::from_bytes(&forest.tree(2).top),
+                proof.outer,
+            ),
+        ]
+        .into();
+        assert!(crate::merkle::verify_chain(&chain, forest.root()));
+
+        forest.remove_element(2, 5).unwrap();
+        forest.remove_element(7, 1).unwrap();
+        assert_eq!(forest.root(), empty_root);
+        assert!(forest.add_element(16, 0, &element).is_err());
+        assert!(forest.add_element(0, 8, &element).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Element, MerkleTree, Proof, Root};
+use crate::error::MerkleError;
+use crate::hasher::{Hasher, Sha256Hasher};
+use ff::{PrimeField, PrimeFieldBits};
+
+// A tree that can no longer be written: only the read methods are exposed. `thaw` hands the
+// mutable tree back.
+#[derive(Clone, Debug)]
+pub struct FrozenTree<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    tree: MerkleTree<F, H, N>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    pub fn freeze(self) -> FrozenTree<F, H, N> {
+        FrozenTree { tree: self }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> FrozenTree<F, H, N> {
+    pub fn thaw(self) -> MerkleTree<F, H, N> {
+        self.tree
+    }
+
+    pub fn root(&self) -> Root<N> {
+        self.tree.root()
+    }
+
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    pub fn get_element(&self, bits_index: &[bool]) -> Option<Element<F>> {
+        self.tree.get_element(bits_index)
+    }
+
+    pub fn contains(&self, bits_index: &[bool]) -> bool {
+        self.tree.contains(bits_index)
+    }
+
+    pub fn prove(&self, bits_index: &[bool]) -> Result<Proof<H, N>, MerkleError> {
+        self.tree.prove(bits_index)
+    }
+
+    pub fn prove_at(&self, index: u64) -> Result<Proof<H, N>, MerkleError> {
+        self.tree.prove_at(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::convert_to_bits;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn frozen_tree_reads_like_the_tree() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        let element = Element::single(Fp::from(3));
+        let bits_index = convert_to_bits(4, 7).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+
+        let frozen = tree.clone().freeze();
+        assert_eq!(frozen.root(), tree.root());
+        assert_eq!(frozen.height(), 4);
+        assert_eq!(frozen.get_element(&bits_index), Some(element.clone()));
+        assert!(frozen.contains(&bits_index));
+        assert_eq!(frozen.prove(&bits_index).unwrap(), proof);
+        assert_eq!(frozen.prove_at(7).unwrap(), proof);
+
+        let mut thawed = frozen.thaw();
+        thawed.remove_element(bits_index).unwrap();
+        assert_ne!(thawed.root(), tree.root());
+    }
+}
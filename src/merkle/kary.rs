@@ -0,0 +1,330 @@
+use super::{check_element, Element, NodeMap, NodeSet};
+use crate::error::MerkleError;
+use crate::hasher::{digests_match, Digest, Hasher, Sha256Hasher};
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// A tree whose internal nodes have `ARITY` children each. Positions are base-`ARITY` digits,
+// most significant first, and a proof carries the `ARITY - 1` other children at every level.
+// With `ARITY = 2` and the same hasher, roots and proofs agree with `MerkleTree`.
+#[derive(Clone, Debug)]
+pub struct KaryTree<
+    F: PrimeField + PrimeFieldBits,
+    const ARITY: usize,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    pub top: Digest<N>,
+    pub data_store: NodeMap<Digest<N>, [Digest<N>; ARITY]>,
+    height: usize,
+    default_hashes: Vec<Digest<N>>,
+    leaves: BTreeMap<Vec<usize>, Element<F>>,
+    live_nodes: usize,
+    hasher: H,
+}
+
+impl<F: PrimeField + PrimeFieldBits, const ARITY: usize, H: Hasher<F, N>, const N: usize>
+    KaryTree<F, ARITY, H, N>
+{
+    pub fn initialize(empty_value: Element<F>, height: usize) -> Self {
+        Self::initialize_with_hasher(empty_value, height, H::default())
+    }
+
+    pub fn initialize_with_hasher(empty_value: Element<F>, height: usize, hasher: H) -> Self {
+        const { assert!(ARITY >= 2, "a tree needs at least two children per node") };
+        let mut default_hashes = vec![empty_value.compute_hash_with(&hasher)];
+        let mut data_store = NodeMap::new();
+        for level in 0..height {
+            let children = [default_hashes[level]; ARITY];
+            let parent = hasher.hash_children(&children);
+            data_store.insert(parent, children);
+            default_hashes.push(parent);
+        }
+        Self {
+            top: default_hashes[height],
+            data_store,
+            height,
+            default_hashes,
+            leaves: BTreeMap::new(),
+            live_nodes: height,
+            hasher,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn default_hash_at_level(&self, level: usize) -> &Digest<N> {
+        &self.default_hashes[level]
+    }
+
+    pub fn add_element(
+        &mut self,
+        digits: Vec<usize>,
+        element: &Element<F>,
+    ) -> Result<(), MerkleError> {
+        check_element(element)?;
+        let leaf_hash = element.compute_hash_with(&self.hasher);
+        self.write_leaf(&digits, leaf_hash)?;
+        if leaf_hash == self.default_hashes[0] {
+            self.leaves.remove(&digits);
+        } else {
+            self.leaves.insert(digits, element.clone());
+        }
+        Ok(())
+    }
+
+    pub fn add_element_at(&mut self, index: u64, element: &Element<F>) -> Result<(), MerkleError> {
+        self.add_element(convert_to_digits(ARITY, self.height, index)?, element)
+    }
+
+    pub fn remove_element(&mut self, digits: Vec<usize>) -> Result<(), MerkleError> {
+        self.write_leaf(&digits, self.default_hashes[0])?;
+        self.leaves.remove(&digits);
+        Ok(())
+    }
+
+    pub fn get_element(&self, digits: &[usize]) -> Option<Element<F>> {
+        self.leaves.get(digits).cloned()
+    }
+
+    fn write_leaf(&mut self, digits: &[usize], leaf_hash: Digest<N>) -> Result<(), MerkleError> {
+        let path = self.get_path_children(digits)?;
+        let mut current_hash = leaf_hash;
+        for (mut children, &digit) in path.into_iter().zip(digits).rev() {
+            children[digit] = current_hash;
+            current_hash = self.hasher.hash_children(&children);
+            self.data_store.insert(current_hash, children);
+        }
+        self.top = current_hash;
+        if self.data_store.len() > 2 * self.live_nodes + self.height {
+            self.prune();
+        }
+        Ok(())
+    }
+
+    pub fn prune(&mut self) {
+        let mut live = NodeSet::new();
+        let mut pending = vec![self.top];
+        while let Some(hash) = pending.pop() {
+            if let Some(children) = self.data_store.get(&hash) {
+                if live.insert(hash) {
+                    pending.extend_from_slice(children);
+                }
+            }
+        }
+        self.data_store.retain(|hash, _| live.contains(hash));
+        self.live_nodes = self.data_store.len();
+    }
+
+    fn check_index(&self, digits: &[usize]) -> Result<(), MerkleError> {
+        if digits.len() != self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: digits.len(),
+            });
+        }
+        if let Some(&digit) = digits.iter().find(|&&digit| digit >= ARITY) {
+            return Err(MerkleError::IndexOutOfRange {
+                index: digit as u64,
+                height: self.height,
+            });
+        }
+        Ok(())
+    }
+
+    // The children of every node on the path, from the root down.
+    fn get_path_children(&self, digits: &[usize]) -> Result<Vec<[Digest<N>; ARITY]>, MerkleError> {
+        self.check_index(digits)?;
+        let mut node_hash = self.top;
+        let mut path = Vec::with_capacity(digits.len());
+        for &digit in digits {
+            let children = *self
+                .data_store
+                .get(&node_hash)
+                .ok_or_else(|| MerkleError::MissingNode(node_hash.to_vec()))?;
+            node_hash = children[digit];
+            path.push(children);
+        }
+        Ok(path)
+    }
+
+    pub fn get_sibling_hashes(&self, digits: &[usize]) -> Result<Vec<Vec<Digest<N>>>, MerkleError> {
+        let path = self.get_path_children(digits)?;
+        Ok(path
+            .iter()
+            .zip(digits)
+            .map(|(children, &digit)| {
+                let mut siblings = children.to_vec();
+                siblings.remove(digit);
+                siblings
+            })
+            .collect())
+    }
+
+    pub fn prove(&self, digits: &[usize]) -> Result<KaryProof<H, N>, MerkleError> {
+        Ok(KaryProof::new(self.get_sibling_hashes(digits)?))
+    }
+
+    pub fn prove_at(&self, index: u64) -> Result<KaryProof<H, N>, MerkleError> {
+        self.prove(&convert_to_digits(ARITY, self.height, index)?)
+    }
+}
+
+// Base-`arity` digits of `index`, most significant first. The arity-2 case is `convert_to_bits`.
+pub fn convert_to_digits(
+    arity: usize,
+    depth: usize,
+    index: u64,
+) -> Result<Vec<usize>, MerkleError> {
+    let mut digits = Vec::with_capacity(depth);
+    let mut rest = index;
+    for _ in 0..depth {
+        digits.push((rest % arity as u64) as usize);
+        rest /= arity as u64;
+    }
+    if rest != 0 {
+        return Err(MerkleError::IndexOutOfRange {
+            index,
+            height: depth,
+        });
+    }
+    digits.reverse();
+    Ok(digits)
+}
+
+// `sibling_hashes[level]` holds the other children of the node at `level`, in child order with
+// the proven position left out.
+#[derive(Clone, Debug)]
+pub struct KaryProof<H = Sha256Hasher, const N: usize = 32> {
+    pub sibling_hashes: Vec<Vec<Digest<N>>>,
+    pub _marker: PhantomData<H>,
+}
+
+impl<H, const N: usize> KaryProof<H, N> {
+    pub fn new(sibling_hashes: Vec<Vec<Digest<N>>>) -> Self {
+        Self {
+            sibling_hashes,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn calculate_root<F: PrimeField + PrimeFieldBits>(
+        &self,
+        digits: &[usize],
+        element: &Element<F>,
+    ) -> Result<Digest<N>, MerkleError>
+    where
+        H: Hasher<F, N>,
+    {
+        if self.sibling_hashes.len() != digits.len() {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.sibling_hashes.len(),
+                got: digits.len(),
+            });
+        }
+        let hasher = H::default();
+        let mut current_hash = element.compute_hash_with(&hasher);
+        for (siblings, &digit) in self.sibling_hashes.iter().zip(digits).rev() {
+            if digit > siblings.len() {
+                return Err(MerkleError::IndexOutOfRange {
+                    index: digit as u64,
+                    height: digits.len(),
+                });
+            }
+            let mut children = siblings.clone();
+            children.insert(digit, current_hash);
+            current_hash = hasher.hash_children(&children);
+        }
+        Ok(current_hash)
+    }
+
+    pub fn validate<F: PrimeField + PrimeFieldBits>(
+        &self,
+        digits: &[usize],
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root(digits, element)
+            .is_ok_and(|root| digests_match(&root, root_hash.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{convert_to_bits, MerkleTree};
+    use pasta_curves::Fp;
+
+    fn element(i: u64) -> Element<Fp> {
+        Element {
+            value: vec![Fp::from(i + 1)],
+        }
+    }
+
+    #[test]
+    fn arity_two_matches_the_binary_tree() {
+        const HEIGHT: usize = 8;
+        let mut binary = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let mut kary = KaryTree::<Fp, 2>::initialize(Element::default(), HEIGHT);
+        assert_eq!(kary.top, binary.top);
+        for index in [0, 5, 200, 255] {
+            binary.add_element_at(index, &element(index)).unwrap();
+            kary.add_element_at(index, &element(index)).unwrap();
+        }
+        assert_eq!(kary.top, binary.top);
+
+        let digits = convert_to_digits(2, HEIGHT, 200).unwrap();
+        let bits = convert_to_bits(HEIGHT, 200).unwrap();
+        assert_eq!(digits, bits.iter().map(|&b| b as usize).collect::<Vec<_>>());
+        let siblings: Vec<Digest> = kary.prove(&digits).unwrap().sibling_hashes.concat();
+        assert_eq!(siblings, binary.prove(&bits).unwrap().sibling_hashes);
+    }
+
+    #[test]
+    fn four_ary_proofs() {
+        const HEIGHT: usize = 5;
+        let mut tree = KaryTree::<Fp, 4>::initialize(Element::default(), HEIGHT);
+        let empty_root = tree.top;
+        let indices = [0, 3, 4, 17, 1023];
+        for index in indices {
+            tree.add_element_at(index, &element(index)).unwrap();
+        }
+        for index in indices {
+            let digits = convert_to_digits(4, HEIGHT, index).unwrap();
+            let proof = tree.prove(&digits).unwrap();
+            assert_eq!(proof.sibling_hashes.len(), HEIGHT);
+            assert!(proof.sibling_hashes.iter().all(|level| level.len() == 3));
+            assert!(proof.validate(&digits, &element(index), tree.top));
+            assert!(!proof.validate(&digits, &element(index + 1), tree.top));
+            assert_eq!(
+                tree.get_element(&digits).unwrap().value,
+                element(index).value
+            );
+        }
+
+        assert_eq!(
+            tree.add_element_at(1024, &element(0)),
+            Err(MerkleError::IndexOutOfRange {
+                index: 1024,
+                height: HEIGHT
+            })
+        );
+        assert!(tree.add_element(vec![0, 0, 4, 0, 0], &element(0)).is_err());
+
+        for index in indices {
+            let digits = convert_to_digits(4, HEIGHT, index).unwrap();
+            tree.remove_element(digits).unwrap();
+        }
+        assert_eq!(tree.top, empty_root);
+        tree.prune();
+        assert_eq!(tree.data_store.len(), HEIGHT);
+    }
+}
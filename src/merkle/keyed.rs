@@ -0,0 +1,112 @@
+use super::{Element, MerkleTree, Proof};
+use crate::error::MerkleError;
+use crate::hasher::Hasher;
+use alloc::vec::Vec;
+use ff::{PrimeField, PrimeFieldBits};
+use sha2::{Digest as _, Sha256};
+
+const KEY_DOMAIN: &[u8] = b"zk-imp key index";
+
+// Maps an arbitrary key to a `height`-bit position by SHA-256 over a domain tag, a block counter
+// and the key, taking bits most-significant first across as many blocks as the height needs.
+// Distinct keys collide with probability about n^2 / 2^(height + 1) for n keys, so small heights
+// fill up fast; `MerkleTree::add_by_key` hands back whatever it replaced so a collision shows.
+pub fn key_to_bits(height: usize, key: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(height);
+    for block in 0u32.. {
+        if bits.len() == height {
+            break;
+        }
+        let digest = Sha256::new()
+            .chain_update(KEY_DOMAIN)
+            .chain_update(block.to_le_bytes())
+            .chain_update(key)
+            .finalize();
+        let remaining = height - bits.len();
+        bits.extend(
+            digest
+                .iter()
+                .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+                .take(remaining),
+        );
+    }
+    bits
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    pub fn index_for_key(&self, key: &[u8]) -> Vec<bool> {
+        key_to_bits(self.height, key)
+    }
+
+    // Returns the element previously at the key's position. `Some` for a key never written
+    // before means another key hashed to the same position.
+    pub fn add_by_key(
+        &mut self,
+        key: &[u8],
+        element: &Element<F>,
+    ) -> Result<Option<Element<F>>, MerkleError> {
+        self.insert(self.index_for_key(key), element)
+    }
+
+    pub fn get_by_key(&self, key: &[u8]) -> Option<Element<F>> {
+        self.get_element(&self.index_for_key(key))
+    }
+
+    pub fn prove_by_key(&self, key: &[u8]) -> Result<Proof<H, N>, MerkleError> {
+        self.prove(&self.index_for_key(key))
+    }
+}
+
+impl<H, const N: usize> Proof<H, N> {
+    pub fn validate_by_key<F: PrimeField + PrimeFieldBits>(
+        &self,
+        key: &[u8],
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let bits_index = key_to_bits(self.expected_height(), key);
+        self.validate(bits_index, element, root_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn keyed_writes_and_proofs() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 160);
+        let alice = Element::new(vec![Fp::from(100)]).unwrap();
+        let bob = Element::new(vec![Fp::from(200)]).unwrap();
+        assert_eq!(tree.add_by_key(b"alice", &alice), Ok(None));
+        assert_eq!(tree.add_by_key(b"bob", &bob), Ok(None));
+        assert_eq!(tree.get_by_key(b"alice"), Some(alice.clone()));
+
+        let proof = tree.prove_by_key(b"alice").unwrap();
+        assert!(proof.validate_by_key(b"alice", &alice, tree.top));
+        assert!(!proof.validate_by_key(b"bob", &alice, tree.top));
+        assert_eq!(tree.index_for_key(b"carol"), key_to_bits(160, b"carol"));
+        assert_eq!(
+            key_to_bits(300, b"carol")[..160],
+            key_to_bits(160, b"carol")
+        );
+    }
+
+    #[test]
+    fn small_heights_expose_collisions() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 2);
+        let mut replaced = 0;
+        for key in 0u8..5 {
+            let element = Element::new(vec![Fp::from(key as u64 + 1)]).unwrap();
+            if tree.add_by_key(&[key], &element).unwrap().is_some() {
+                replaced += 1;
+            }
+        }
+        // Five keys cannot fit in four positions.
+        assert!(replaced >= 1);
+    }
+}
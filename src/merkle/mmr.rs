@@ -0,0 +1,282 @@
+use super::{check_element, Element, Leaf, Root};
+use crate::error::MerkleError;
+use crate::hasher::{digests_match, Digest, Hasher, Sha256Hasher};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// An append-only Merkle mountain range: leaves are grouped into perfect subtrees ("mountains")
+// following the binary digits of the leaf count, largest first, and the root bags their peaks
+// from the right, `hash_nodes(peak, bagged)`. There is no preset height. Each mountain hashes
+// exactly like a `MerkleTree` over the same leaves.
+#[derive(Clone, Debug)]
+pub struct Mmr<F: PrimeField + PrimeFieldBits, H: Hasher<F, N> = Sha256Hasher, const N: usize = 32>
+{
+    // `levels[l][i]` is the root of the `i`th complete block of `2^l` leaves.
+    levels: Vec<Vec<Digest<N>>>,
+    hasher: H,
+    _marker: PhantomData<F>,
+}
+
+// `sibling_hashes` climbs from the leaf to its mountain's peak, bottom-up, unlike `Proof`.
+// `peaks` holds every other peak, left to right.
+#[derive(Clone, Debug)]
+pub struct MmrProof<H = Sha256Hasher, const N: usize = 32> {
+    pub leaf_count: u64,
+    pub sibling_hashes: Vec<Digest<N>>,
+    pub peaks: Vec<Digest<N>>,
+    pub _marker: PhantomData<H>,
+}
+
+// (first leaf, height) of each mountain for `leaf_count` leaves, left to right.
+fn mountains(leaf_count: u64) -> impl Iterator<Item = (u64, usize)> {
+    (0..u64::BITS as usize)
+        .rev()
+        .filter(move |&height| leaf_count >> height & 1 == 1)
+        .scan(0u64, |start, height| {
+            let mountain = (*start, height);
+            *start += 1 << height;
+            Some(mountain)
+        })
+}
+
+fn bag<F: PrimeField, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    peaks: &[Digest<N>],
+) -> Option<Digest<N>> {
+    let (last, rest) = peaks.split_last()?;
+    Some(
+        rest.iter()
+            .rev()
+            .fold(*last, |bagged, peak| hasher.hash_nodes(peak, &bagged)),
+    )
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> Mmr<F, H, N> {
+    pub fn new() -> Self {
+        Self::with_hasher(H::default())
+    }
+
+    pub fn with_hasher(hasher: H) -> Self {
+        Self {
+            levels: Vec::new(),
+            hasher,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.levels.first().map_or(0, |leaves| leaves.len() as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the new leaf's index.
+    pub fn append(&mut self, element: &Element<F>) -> Result<u64, MerkleError> {
+        check_element(element)?;
+        Ok(self.append_leaf(element))
+    }
+
+    // Only the digest is kept, so any `Leaf` can be appended.
+    pub fn append_leaf<L: Leaf<F>>(&mut self, leaf: &L) -> u64 {
+        let index = self.len();
+        let mut hash = leaf.hash_with(&self.hasher);
+        for level in 0.. {
+            if self.levels.len() == level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].push(hash);
+            let nodes = &self.levels[level];
+            if nodes.len() % 2 == 1 {
+                break;
+            }
+            hash = self
+                .hasher
+                .hash_nodes(&nodes[nodes.len() - 2], &nodes[nodes.len() - 1]);
+        }
+        index
+    }
+
+    fn peaks(&self) -> Vec<Digest<N>> {
+        mountains(self.len())
+            .map(|(start, height)| self.levels[height][(start >> height) as usize])
+            .collect()
+    }
+
+    // `None` until the first append.
+    pub fn root(&self) -> Option<Root<N>> {
+        bag(&self.hasher, &self.peaks()).map(Root)
+    }
+
+    pub fn prove(&self, index: u64) -> Result<MmrProof<H, N>, MerkleError> {
+        let leaf_count = self.len();
+        let Some((position, (_, height))) = mountains(leaf_count)
+            .enumerate()
+            .find(|&(_, (start, height))| index >= start && index - start < 1 << height)
+        else {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                height: self.levels.len().saturating_sub(1),
+            });
+        };
+        let sibling_hashes = (0..height)
+            .map(|level| self.levels[level][((index >> level) ^ 1) as usize])
+            .collect();
+        let mut peaks = self.peaks();
+        peaks.remove(position);
+        Ok(MmrProof {
+            leaf_count,
+            sibling_hashes,
+            peaks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> Default for Mmr<F, H, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H, const N: usize> MmrProof<H, N> {
+    pub fn validate<F: PrimeField + PrimeFieldBits>(
+        &self,
+        index: u64,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.validate_leaf(index, element, root_hash)
+    }
+
+    pub fn validate_leaf<F: PrimeField + PrimeFieldBits, L: Leaf<F>>(
+        &self,
+        index: u64,
+        leaf: &L,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        let hasher = H::default();
+        let Some((position, (_, height))) = mountains(self.leaf_count)
+            .enumerate()
+            .find(|&(_, (start, height))| index >= start && index - start < 1 << height)
+        else {
+            return false;
+        };
+        if self.sibling_hashes.len() != height
+            || self.peaks.len() + 1 != self.leaf_count.count_ones() as usize
+        {
+            return false;
+        }
+        let peak = self.sibling_hashes.iter().enumerate().fold(
+            leaf.hash_with(&hasher),
+            |hash, (level, sibling)| {
+                if index >> level & 1 == 1 {
+                    hasher.hash_nodes(sibling, &hash)
+                } else {
+                    hasher.hash_nodes(&hash, sibling)
+                }
+            },
+        );
+        let mut peaks = self.peaks.clone();
+        peaks.insert(position, peak);
+        bag(&hasher, &peaks).is_some_and(|root| digests_match(&root, root_hash.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::MerkleTree;
+    use pasta_curves::Fp;
+
+    fn element(i: u64) -> Element<Fp> {
+        Element::new(vec![Fp::from(i + 1)]).unwrap()
+    }
+
+    #[test]
+    fn every_leaf_proves_as_the_range_grows() {
+        let mut mmr = Mmr::<Fp>::new();
+        assert_eq!(mmr.root(), None);
+        let mut roots = Vec::new();
+        for i in 0..13 {
+            assert_eq!(mmr.append(&element(i)), Ok(i));
+            let root = mmr.root().unwrap();
+            for j in 0..=i {
+                let proof = mmr.prove(j).unwrap();
+                assert!(proof.validate(j, &element(j), root));
+                assert!(!proof.validate(j, &element(j + 1), root));
+            }
+            assert!(mmr.prove(i + 1).is_err());
+            roots.push(root);
+        }
+        roots.dedup();
+        assert_eq!(roots.len(), 13);
+
+        let proof = mmr.prove(5).unwrap();
+        assert!(!proof.validate(4, &element(5), roots[12]));
+        assert!(!proof.validate(5, &element(5), roots[11]));
+    }
+
+    // Hashes its fields directly rather than as an `Element`.
+    struct Transfer {
+        from: u64,
+        amount: u64,
+    }
+
+    impl Leaf<Fp> for Transfer {
+        fn hash_with<H: Hasher<Fp, N>, const N: usize>(&self, hasher: &H) -> Digest<N> {
+            hasher.hash_leaf(&[
+                Fp::from(self.from).to_repr(),
+                Fp::from(self.amount).to_repr(),
+            ])
+        }
+    }
+
+    #[test]
+    fn custom_leaves_append_and_prove() {
+        let mut mmr = Mmr::<Fp>::new();
+        for from in 0..5 {
+            mmr.append_leaf(&Transfer { from, amount: 10 });
+        }
+        let root = mmr.root().unwrap();
+        let proof = mmr.prove(3).unwrap();
+        assert!(proof.validate_leaf::<Fp, _>(
+            3,
+            &Transfer {
+                from: 3,
+                amount: 10
+            },
+            root
+        ));
+        assert!(!proof.validate_leaf::<Fp, _>(
+            3,
+            &Transfer {
+                from: 3,
+                amount: 11
+            },
+            root
+        ));
+        // The same fields as an `Element` hash identically.
+        let element = Element::new(vec![Fp::from(3), Fp::from(10)]).unwrap();
+        assert!(proof.validate(3, &element, root));
+    }
+
+    #[test]
+    fn a_single_mountain_is_a_binary_tree() {
+        let mut mmr = Mmr::<Fp>::new();
+        let elements: Vec<_> = (0..8).map(element).collect();
+        for element in &elements {
+            mmr.append(element).unwrap();
+        }
+        let tree = MerkleTree::<Fp>::from_elements(Element::default(), 3, elements).unwrap();
+        assert_eq!(mmr.root(), Some(tree.root()));
+    }
+}
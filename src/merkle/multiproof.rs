@@ -0,0 +1,181 @@
+use super::{Element, MerkleTree};
+use crate::error::MerkleError;
+use crate::hasher::{digests_match, Digest, Hasher, Sha256Hasher};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// Siblings are listed in the order a depth-first, left-to-right walk over the proven paths meets
+// them; any node that lies on one of the paths is recomputed by the verifier and never included.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct MultiProof<H = Sha256Hasher, const N: usize = 32> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::digests"))]
+    pub sibling_hashes: Vec<Digest<N>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _marker: PhantomData<H>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    pub fn prove_many(&self, indices: &[Vec<bool>]) -> Result<MultiProof<H, N>, MerkleError> {
+        let mut sorted: Vec<&[bool]> = Vec::with_capacity(indices.len());
+        for bits_index in indices {
+            self.check_index(bits_index)?;
+            sorted.push(bits_index);
+        }
+        sorted.sort();
+        sorted.dedup();
+        let mut sibling_hashes = Vec::new();
+        if !sorted.is_empty() {
+            self.collect_siblings(&self.top, 0, &sorted, &mut sibling_hashes)?;
+        }
+        Ok(MultiProof {
+            sibling_hashes,
+            _marker: PhantomData,
+        })
+    }
+
+    fn collect_siblings(
+        &self,
+        node_hash: &Digest<N>,
+        depth: usize,
+        indices: &[&[bool]],
+        out: &mut Vec<Digest<N>>,
+    ) -> Result<(), MerkleError> {
+        if depth == self.height {
+            return Ok(());
+        }
+        let (left, right) = &self.children_at(node_hash, &indices[0][..depth])?;
+        let split = indices.partition_point(|bits_index| !bits_index[depth]);
+        let (lower, upper) = indices.split_at(split);
+        if lower.is_empty() {
+            out.push(*left);
+        } else {
+            self.collect_siblings(left, depth + 1, lower, out)?;
+        }
+        if upper.is_empty() {
+            out.push(*right);
+        } else {
+            self.collect_siblings(right, depth + 1, upper, out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<H, const N: usize> MultiProof<H, N> {
+    pub fn calculate_root<F: PrimeField + PrimeFieldBits>(
+        &self,
+        leaves: &[(Vec<bool>, Element<F>)],
+    ) -> Option<Digest<N>>
+    where
+        H: Hasher<F, N>,
+    {
+        let hasher = H::default();
+        let height = leaves.first()?.0.len();
+        let mut sorted = BTreeMap::new();
+        for (bits_index, element) in leaves {
+            if bits_index.len() != height {
+                return None;
+            }
+            let leaf_hash = element.compute_hash_with(&hasher);
+            if let Some(previous) = sorted.insert(bits_index.as_slice(), leaf_hash) {
+                if previous != leaf_hash {
+                    return None;
+                }
+            }
+        }
+        let sorted: Vec<(&[bool], Digest<N>)> = sorted.into_iter().collect();
+        let mut siblings = self.sibling_hashes.iter();
+        let root = fold(&hasher, 0, height, &sorted, &mut siblings)?;
+        siblings.next().is_none().then_some(root)
+    }
+
+    pub fn validate_many<F: PrimeField + PrimeFieldBits>(
+        &self,
+        leaves: &[(Vec<bool>, Element<F>)],
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        self.calculate_root(leaves)
+            .is_some_and(|root| digests_match(&root, root_hash.as_ref()))
+    }
+}
+
+fn fold<'a, F: PrimeField, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    depth: usize,
+    height: usize,
+    leaves: &[(&[bool], Digest<N>)],
+    siblings: &mut impl Iterator<Item = &'a Digest<N>>,
+) -> Option<Digest<N>> {
+    if depth == height {
+        return Some(leaves[0].1);
+    }
+    let split = leaves.partition_point(|(bits_index, _)| !bits_index[depth]);
+    let (lower, upper) = leaves.split_at(split);
+    let left = if lower.is_empty() {
+        *siblings.next()?
+    } else {
+        fold(hasher, depth + 1, height, lower, siblings)?
+    };
+    let right = if upper.is_empty() {
+        *siblings.next()?
+    } else {
+        fold(hasher, depth + 1, height, upper, siblings)?
+    };
+    Some(hasher.hash_nodes(&left, &right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::convert_to_bits;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn multiproof_agrees_with_single_proofs() {
+        const HEIGHT: usize = 32;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let indices = [0u64, 1, 2, 7, 1 << 20, (1 << 32) - 1, 12345, 12346, 99, 3];
+        let mut leaves = Vec::new();
+        for &index in &indices {
+            let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+            let element = Element {
+                value: vec![Fp::from(index + 1)],
+            };
+            tree.add_element(bits_index.clone(), &element).unwrap();
+            leaves.push((bits_index, element));
+        }
+
+        let bits: Vec<Vec<bool>> = leaves.iter().map(|(b, _)| b.clone()).collect();
+        let multiproof = tree.prove_many(&bits).unwrap();
+        assert!(multiproof.sibling_hashes.len() < indices.len() * HEIGHT);
+        assert!(multiproof.validate_many(&leaves, tree.top));
+        for (bits_index, element) in &leaves {
+            let proof = tree.prove(bits_index).unwrap();
+            assert!(proof.validate(bits_index.clone(), element, tree.top));
+        }
+
+        let mut tampered = leaves.clone();
+        tampered[4].1 = Element::default();
+        let single = tree.prove(&tampered[4].0).unwrap();
+        assert!(!single.validate(tampered[4].0.clone(), &tampered[4].1, tree.top));
+        assert!(!multiproof.validate_many(&tampered, tree.top));
+
+        assert!(!multiproof.validate_many(&leaves[1..], tree.top));
+        assert!(!multiproof.validate_many::<Fp>(&[], &tree.top));
+
+        let single = tree.prove_many(&bits[..1]).unwrap();
+        let mut expected = tree.prove(&bits[0]).unwrap().sibling_hashes;
+        let mut got = single.sibling_hashes.clone();
+        expected.sort();
+        got.sort();
+        assert_eq!(got, expected);
+        assert!(single.validate_many(&leaves[..1], tree.top));
+    }
+}
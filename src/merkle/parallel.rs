@@ -0,0 +1,109 @@
+use super::{Element, MerkleTree, NodeMap, Proof};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher};
+use ff::{PrimeField, PrimeFieldBits};
+use rayon::prelude::*;
+
+// Number of top levels split off into independent subtrees, giving up to 2^SPLIT_DEPTH tasks.
+const SPLIT_DEPTH: usize = 6;
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N> + Sync, const N: usize> MerkleTree<F, H, N> {
+    pub fn add_batch_parallel(
+        &mut self,
+        entries: Vec<(Vec<bool>, Element<F>)>,
+    ) -> Result<(), MerkleError> {
+        self.start_op();
+        let sorted = self.sort_batch(entries)?;
+        if sorted.is_empty() {
+            return Ok(());
+        }
+        let split_depth = self.height.min(SPLIT_DEPTH);
+        let mut hashed: Vec<(Vec<bool>, Digest<N>)> = sorted
+            .par_iter()
+            .map(|(bits_index, element)| (bits_index.clone(), self.leaf_hash(element)))
+            .collect();
+        let groups: Vec<&mut [(Vec<bool>, Digest<N>)]> = hashed
+            .chunk_by_mut(|a, b| a.0[..split_depth] == b.0[..split_depth])
+            .collect();
+
+        let subtrees = groups
+            .into_par_iter()
+            .map(|group| {
+                let prefix = group[0].0[..split_depth].to_vec();
+                let mut created = NodeMap::new();
+                let node_hash = self.subtree_root(&prefix)?;
+                let hash =
+                    self.update_subtree(node_hash, split_depth, self.height, group, &mut created)?;
+                Ok(((prefix, hash), created))
+            })
+            .collect::<Result<Vec<_>, MerkleError>>()?;
+
+        let mut created = NodeMap::new();
+        let mut subtree_roots = Vec::with_capacity(subtrees.len());
+        for (root, nodes) in subtrees {
+            created.extend(nodes);
+            subtree_roots.push(root);
+        }
+        let top = self.update_subtree(self.top, 0, split_depth, &subtree_roots, &mut created)?;
+        self.store_nodes(created);
+        self.set_top(top);
+        for ((bits_index, element), (_, leaf_hash)) in sorted.into_iter().zip(&hashed) {
+            self.store_leaf(bits_index, element, leaf_hash);
+        }
+        Ok(())
+    }
+
+    // Same proofs, in the same order, as calling `prove_at` for each index. Proving only reads
+    // the store, so the indices are spread across the pool with no locking.
+    pub fn prove_many_parallel(&self, indices: &[u64]) -> Result<Vec<Proof<H, N>>, MerkleError>
+    where
+        H: Send,
+    {
+        indices
+            .par_iter()
+            .map(|&index| self.prove_at(index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::convert_to_bits;
+    use pasta_curves::Fp;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn parallel_batch_matches_serial_batch() {
+        for height in [0, 3, 20] {
+            let mut serial = MerkleTree::<Fp>::initialize(Element::default(), height);
+            let mut parallel = serial.clone();
+            let mut rng = ChaCha8Rng::seed_from_u64(height as u64);
+            let entries: Vec<(Vec<bool>, Element<Fp>)> = (0..300)
+                .map(|i| {
+                    let index = rng.gen::<u64>() & ((1 << height) - 1);
+                    let element = Element {
+                        value: vec![Fp::from(i + 1)],
+                    };
+                    (convert_to_bits(height, index).unwrap(), element)
+                })
+                .collect();
+
+            serial.add_batch(entries.clone()).unwrap();
+            parallel.add_batch_parallel(entries.clone()).unwrap();
+            assert_eq!(parallel.top, serial.top);
+            let indices: Vec<u64> = (0..300).map(|i| i & ((1 << height) - 1)).collect();
+            let proofs = parallel.prove_many_parallel(&indices).unwrap();
+            for (&index, proof) in indices.iter().zip(&proofs) {
+                assert_eq!(*proof, parallel.prove_at(index).unwrap());
+            }
+            assert!(parallel.prove_many_parallel(&[1 << height]).is_err());
+            for (bits_index, _) in &entries {
+                let element = parallel.get_element(bits_index).unwrap();
+                let proof = parallel.prove(bits_index).unwrap();
+                assert!(proof.validate(bits_index.clone(), &element, parallel.top));
+            }
+        }
+    }
+}
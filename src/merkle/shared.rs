@@ -0,0 +1,146 @@
+use super::{convert_to_bits, sibling_hashes, Element, MerkleTree, NodeMap, Proof, Root};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher, Sha256Hasher};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// A frozen, read-only view of a tree at one root. Clones share the node and leaf stores, so a
+// snapshot can be handed to any number of reader threads while the live tree keeps changing.
+#[derive(Debug)]
+pub struct TreeSnapshot<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    top: Digest<N>,
+    height: usize,
+    data_store: Arc<NodeMap<Digest<N>, (Digest<N>, Digest<N>)>>,
+    default_hashes: Arc<Vec<Digest<N>>>,
+    leaves: Arc<BTreeMap<Vec<bool>, Element<F>>>,
+    leaf_digests: Arc<BTreeMap<Vec<bool>, Digest<N>>>,
+    _marker: PhantomData<H>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> Clone
+    for TreeSnapshot<F, H, N>
+{
+    fn clone(&self) -> Self {
+        Self {
+            top: self.top,
+            height: self.height,
+            data_store: Arc::clone(&self.data_store),
+            default_hashes: Arc::clone(&self.default_hashes),
+            leaves: Arc::clone(&self.leaves),
+            leaf_digests: Arc::clone(&self.leaf_digests),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    // Copies the current nodes and leaves once; publishing a new snapshot after a batch of writes
+    // is what keeps readers up to date.
+    pub fn snapshot(&self) -> TreeSnapshot<F, H, N> {
+        TreeSnapshot {
+            top: self.top,
+            height: self.height,
+            data_store: Arc::new(self.all_nodes().into_owned()),
+            default_hashes: Arc::new(self.default_hashes.clone()),
+            leaves: Arc::new(self.leaves.clone()),
+            leaf_digests: Arc::new(self.leaf_digests.clone()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> TreeSnapshot<F, H, N> {
+    pub fn root(&self) -> Root<N> {
+        Root(self.top)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_element(&self, bits_index: &[bool]) -> Option<Element<F>> {
+        self.leaves.get(bits_index).cloned()
+    }
+
+    pub fn contains(&self, bits_index: &[bool]) -> bool {
+        self.leaves.contains_key(bits_index) || self.leaf_digests.contains_key(bits_index)
+    }
+
+    pub fn get_sibling_hashes(&self, bits_index: &[bool]) -> Result<Vec<Digest<N>>, MerkleError> {
+        sibling_hashes(&self.data_store, &self.default_hashes, self.top, bits_index)
+    }
+
+    pub fn prove(&self, bits_index: &[bool]) -> Result<Proof<H, N>, MerkleError> {
+        if bits_index.len() != self.height {
+            return Err(MerkleError::LengthMismatch {
+                expected: self.height,
+                got: bits_index.len(),
+            });
+        }
+        Ok(Proof::new(self.get_sibling_hashes(bits_index)?))
+    }
+
+    pub fn prove_at(&self, index: u64) -> Result<Proof<H, N>, MerkleError> {
+        self.prove(&convert_to_bits(self.height, index)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pasta_curves::Fp;
+    use std::thread;
+
+    #[test]
+    fn snapshots_serve_proofs_across_threads() {
+        const HEIGHT: usize = 16;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        for i in 0..20 {
+            tree.append(&Element {
+                value: vec![Fp::from(i + 1)],
+            })
+            .unwrap();
+        }
+        let snapshot = tree.snapshot();
+        let root = tree.top;
+
+        thread::scope(|scope| {
+            for reader in 0..4u64 {
+                let snapshot = snapshot.clone();
+                scope.spawn(move || {
+                    for index in (reader..20).step_by(4) {
+                        let bits_index = convert_to_bits(HEIGHT, index).unwrap();
+                        let element = snapshot.get_element(&bits_index).unwrap();
+                        let proof = snapshot.prove(&bits_index).unwrap();
+                        assert!(proof.validate(bits_index, &element, snapshot.root()));
+                    }
+                });
+            }
+            for i in 20..40 {
+                tree.append(&Element {
+                    value: vec![Fp::from(i + 1)],
+                })
+                .unwrap();
+            }
+        });
+
+        assert_eq!(*snapshot.root().as_bytes(), root);
+        assert_ne!(tree.top, root);
+        assert!(!snapshot.contains(&convert_to_bits(HEIGHT, 30).unwrap()));
+        let proof = snapshot.prove_at(3).unwrap();
+        assert!(proof.validate_at(
+            3,
+            &snapshot
+                .get_element(&convert_to_bits(HEIGHT, 3).unwrap())
+                .unwrap(),
+            root
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+use super::{
+    check_element, default_hashes_from, field_from_repr_bytes, Element, MerkleTree, NodeMap,
+};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// Layout, all integers little-endian:
+//   height: u64 | next append index: u64 | top | empty element | domain |
+//   u32 default override count, (u32 level, hash)* | u64 node count, (hash, left, right)* |
+//   u64 leaf count, (packed index bits, element)* | u64 hash leaf count, (packed index bits, hash)*
+// where a byte string is a u32 length followed by its bytes, an element is a u32 field count
+// followed by each value's repr as a byte string, and the domain is a 0 byte for none or a 1 byte
+// followed by the domain as a byte string. Overrides hold, in ascending level order, only the
+// default hashes that differ from those derived from the empty element, as set by
+// `initialize_with_defaults`. Hash leaves are those written by `add_leaf`.
+//
+// Root history is not stored, so a restored tree starts without one. Nodes are written in hash
+// order and leaves in index order, so equal trees serialize to equal bytes.
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn put_element<F: PrimeField + PrimeFieldBits>(out: &mut Vec<u8>, element: &Element<F>) {
+    out.extend_from_slice(&(element.value.len() as u32).to_le_bytes());
+    for v in &element.value {
+        put_bytes(out, v.to_repr().as_ref());
+    }
+}
+
+pub(super) fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut packed = vec![0u8; bits.len().div_ceil(8)];
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            packed[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    packed
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], MerkleError> {
+        if self.bytes.len() < n {
+            return Err(MerkleError::MalformedSnapshot);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, MerkleError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, MerkleError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, MerkleError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, MerkleError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn digest<const N: usize>(&mut self) -> Result<Digest<N>, MerkleError> {
+        self.bytes()?
+            .try_into()
+            .map_err(|_| MerkleError::MalformedSnapshot)
+    }
+
+    fn element<F: PrimeField + PrimeFieldBits>(&mut self) -> Result<Element<F>, MerkleError> {
+        let count = self.u32()? as usize;
+        let mut value = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            let repr = self.bytes()?;
+            value.push(field_from_repr_bytes(&repr).ok_or(MerkleError::NonCanonicalField)?);
+        }
+        Ok(Element { value })
+    }
+
+    fn bits(&mut self, len: usize) -> Result<Vec<bool>, MerkleError> {
+        let packed = self.take(len.div_ceil(8))?;
+        // Set padding bits would give a second encoding of the same index.
+        if !len.is_multiple_of(8) && packed[len / 8] & (0xff >> (len % 8)) != 0 {
+            return Err(MerkleError::MalformedSnapshot);
+        }
+        Ok((0..len)
+            .map(|i| packed[i / 8] & (0x80 >> (i % 8)) != 0)
+            .collect())
+    }
+}
+
+fn derive_default_hashes<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize>(
+    hasher: &H,
+    empty_value: &Element<F>,
+    domain: Option<&[u8]>,
+    height: usize,
+) -> Vec<Digest<N>> {
+    let empty_hash = match domain {
+        Some(domain) => empty_value.compute_domain_hash_with(hasher, domain),
+        None => empty_value.compute_hash_with(hasher),
+    };
+    default_hashes_from(hasher, empty_hash, height)
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    fn derived_default_hashes(&self) -> Vec<Digest<N>> {
+        derive_default_hashes(
+            &self.hasher,
+            &self.empty_value,
+            self.domain.as_deref(),
+            self.height,
+        )
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.height as u64).to_le_bytes());
+        out.extend_from_slice(&self.next_index.to_le_bytes());
+        put_bytes(&mut out, &self.top);
+        put_element(&mut out, &self.empty_value);
+        match &self.domain {
+            Some(domain) => {
+                out.push(1);
+                put_bytes(&mut out, domain);
+            }
+            None => out.push(0),
+        }
+        let derived = self.derived_default_hashes();
+        let overrides: Vec<_> = (0..=self.height)
+            .filter(|&level| self.default_hashes[level] != derived[level])
+            .collect();
+        out.extend_from_slice(&(overrides.len() as u32).to_le_bytes());
+        for level in overrides {
+            out.extend_from_slice(&(level as u32).to_le_bytes());
+            put_bytes(&mut out, &self.default_hashes[level]);
+        }
+        let all_nodes = self.all_nodes();
+        let mut nodes: Vec<_> = all_nodes.iter().collect();
+        nodes.sort_unstable_by_key(|(hash, _)| *hash);
+        out.extend_from_slice(&(nodes.len() as u64).to_le_bytes());
+        for (hash, (left, right)) in nodes {
+            put_bytes(&mut out, hash);
+            put_bytes(&mut out, left);
+            put_bytes(&mut out, right);
+        }
+        out.extend_from_slice(&(self.leaves.len() as u64).to_le_bytes());
+        for (bits_index, element) in &self.leaves {
+            out.extend_from_slice(&pack_bits(bits_index));
+            put_element(&mut out, element);
+        }
+        out.extend_from_slice(&(self.leaf_digests.len() as u64).to_le_bytes());
+        for (bits_index, digest) in &self.leaf_digests {
+            out.extend_from_slice(&pack_bits(bits_index));
+            put_bytes(&mut out, digest);
+        }
+        out
+    }
+
+    pub fn deserialize(bytes: &[u8], height: usize) -> Result<Self, MerkleError> {
+        let mut reader = Reader { bytes };
+        let stored_height = reader.u64()?;
+        if stored_height != height as u64 {
+            return Err(MerkleError::LengthMismatch {
+                expected: height,
+                got: stored_height as usize,
+            });
+        }
+        let next_index = reader.u64()?;
+        let top = reader.digest()?;
+        let empty_value = reader.element()?;
+        let domain = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.bytes()?),
+            _ => return Err(MerkleError::MalformedSnapshot),
+        };
+        let override_count = reader.u32()?;
+        let mut overrides = Vec::new();
+        for _ in 0..override_count {
+            let level = reader.u32()? as usize;
+            if level > height || overrides.last().is_some_and(|&(last, _)| level <= last) {
+                return Err(MerkleError::MalformedSnapshot);
+            }
+            overrides.push((level, reader.digest()?));
+        }
+
+        let node_count = reader.u64()?;
+        let mut data_store = NodeMap::new();
+        for _ in 0..node_count {
+            let hash = reader.digest()?;
+            let left = reader.digest()?;
+            let right = reader.digest()?;
+            data_store.insert(hash, (left, right));
+        }
+
+        let leaf_count = reader.u64()?;
+        let mut leaves = BTreeMap::new();
+        for _ in 0..leaf_count {
+            let bits_index = reader.bits(height)?;
+            let element = reader.element()?;
+            check_element(&element)?;
+            leaves.insert(bits_index, element);
+        }
+        let digest_count = reader.u64()?;
+        let mut leaf_digests = BTreeMap::new();
+        for _ in 0..digest_count {
+            let bits_index = reader.bits(height)?;
+            if leaves.contains_key(&bits_index) {
+                return Err(MerkleError::MalformedSnapshot);
+            }
+            leaf_digests.insert(bits_index, reader.digest()?);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(MerkleError::MalformedSnapshot);
+        }
+
+        let hasher = H::default();
+        let mut default_hashes =
+            derive_default_hashes(&hasher, &empty_value, domain.as_deref(), height);
+        for (level, hash) in overrides {
+            // An override equal to the derived hash would give a second encoding of the tree.
+            if default_hashes[level] == hash {
+                return Err(MerkleError::MalformedSnapshot);
+            }
+            default_hashes[level] = hash;
+        }
+        if top != default_hashes[height] && !data_store.contains_key(&top) {
+            return Err(MerkleError::MissingNode(top.to_vec()));
+        }
+
+        Ok(Self {
+            top,
+            data_store,
+            _marker: PhantomData,
+            height,
+            empty_value,
+            default_hashes,
+            live_nodes: node_count as usize,
+            next_index,
+            leaves,
+            leaf_digests,
+            hasher,
+            root_history: VecDeque::new(),
+            undo_history: VecDeque::new(),
+            history_capacity: 0,
+            domain,
+            max_nodes: None,
+            access: Default::default(),
+            #[cfg(feature = "metrics")]
+            hash_count: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Sha256Hasher;
+    use crate::merkle::convert_to_bits;
+    use pasta_curves::Fp;
+
+    fn sample_tree() -> MerkleTree<Fp> {
+        const HEIGHT: usize = 12;
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        tree.append(&Element::default()).unwrap();
+        for index in [0, 3, 1000, 4095] {
+            let element = Element {
+                value: vec![Fp::from(index), Fp::from(index * 2)],
+            };
+            tree.add_element(convert_to_bits(HEIGHT, index).unwrap(), &element)
+                .unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let tree = sample_tree();
+        let restored = MerkleTree::<Fp>::deserialize(&tree.serialize(), 12).unwrap();
+        assert_eq!(restored.height(), 12);
+        assert_eq!(restored.next_index(), 1);
+        assert_eq!(restored.top, tree.top);
+        assert_eq!(restored.data_store, tree.data_store);
+
+        let bits_index = convert_to_bits(12, 1000).unwrap();
+        let element = restored.get_element(&bits_index).unwrap();
+        assert_eq!(element.value, vec![Fp::from(1000), Fp::from(2000)]);
+        let proof = restored.prove(&bits_index).unwrap();
+        assert!(proof.validate(bits_index, &element, tree.top));
+    }
+
+    #[test]
+    fn domain_trees_round_trip() {
+        let mut tree = MerkleTree::<Fp>::initialize_with_domain(Element::default(), 8, b"app");
+        let empty = MerkleTree::<Fp>::deserialize(&tree.serialize(), 8).unwrap();
+        assert_eq!(empty.domain(), Some(&b"app"[..]));
+        assert!(empty.deep_eq(&tree));
+
+        let element = Element::single(Fp::from(5));
+        tree.add_element_at(3, &element).unwrap();
+        let mut restored = MerkleTree::<Fp>::deserialize(&tree.serialize(), 8).unwrap();
+        assert!(restored.deep_eq(&tree));
+        // Later writes keep hashing leaves in the domain.
+        tree.add_element_at(200, &element).unwrap();
+        restored.add_element_at(200, &element).unwrap();
+        assert_eq!(restored.top, tree.top);
+        let bits_index = convert_to_bits(8, 200).unwrap();
+        let proof = restored.prove(&bits_index).unwrap();
+        assert!(proof.validate_in_domain(&bits_index, &element, b"app", tree.top));
+    }
+
+    #[test]
+    fn custom_default_trees_round_trip() {
+        const HEIGHT: usize = 6;
+        let empty_value = Element::<Fp>::default();
+        let mut defaults = vec![empty_value.compute_hash()];
+        for level in 1..=HEIGHT {
+            defaults.push([level as u8; 32]);
+        }
+        let mut tree =
+            MerkleTree::<Fp>::initialize_with_defaults(empty_value, HEIGHT, defaults).unwrap();
+        let empty = MerkleTree::<Fp>::deserialize(&tree.serialize(), HEIGHT).unwrap();
+        assert!(empty.deep_eq(&tree));
+
+        let element = Element::single(Fp::from(5));
+        tree.add_element_at(9, &element).unwrap();
+        let bytes = tree.serialize();
+        let restored = MerkleTree::<Fp>::deserialize(&bytes, HEIGHT).unwrap();
+        assert!(restored.deep_eq(&tree));
+        for index in [9, 10, 63] {
+            assert_eq!(restored.prove_at(index), tree.prove_at(index));
+        }
+
+        // An override restating the derived hash is a second encoding of the same tree.
+        let empty_value = Element::<Fp>::default();
+        let derived =
+            default_hashes_from::<Fp, _, 32>(&Sha256Hasher, empty_value.compute_hash(), HEIGHT);
+        let mut defaults = derived.clone();
+        defaults[3] = [0; 32];
+        let tree =
+            MerkleTree::<Fp>::initialize_with_defaults(empty_value, HEIGHT, defaults).unwrap();
+        let mut bytes = tree.serialize();
+        let mut entry = [3u32.to_le_bytes(), 32u32.to_le_bytes()].concat();
+        entry.extend_from_slice(&[0; 32]);
+        let at = bytes.windows(entry.len()).position(|w| w == entry).unwrap();
+        bytes[at + 8..at + 40].copy_from_slice(&derived[3]);
+        assert_eq!(
+            MerkleTree::<Fp>::deserialize(&bytes, HEIGHT).unwrap_err(),
+            MerkleError::MalformedSnapshot
+        );
+    }
+
+    #[test]
+    fn equal_trees_serialize_identically() {
+        let tree = sample_tree();
+        let bytes = tree.serialize();
+        assert_eq!(tree.serialize(), bytes);
+        // Separately built stores need not share an iteration order.
+        assert_eq!(sample_tree().serialize(), bytes);
+        let restored = MerkleTree::<Fp>::deserialize(&bytes, 12).unwrap();
+        assert_eq!(restored.serialize(), bytes);
+    }
+
+    #[test]
+    fn truncated_or_mismatched_snapshots_are_rejected() {
+        let tree = sample_tree();
+        let bytes = tree.serialize();
+        for len in [0, 7, 20, bytes.len() / 2, bytes.len() - 1] {
+            assert!(MerkleTree::<Fp>::deserialize(&bytes[..len], 12).is_err());
+        }
+        assert!(MerkleTree::<Fp>::deserialize(&bytes, 11).is_err());
+
+        let mut tree = tree;
+        tree.data_store.remove(&tree.top);
+        assert_eq!(
+            MerkleTree::<Fp>::deserialize(&tree.serialize(), 12).unwrap_err(),
+            MerkleError::MissingNode(tree.top.to_vec())
+        );
+
+        // The snapshot ends with the last leaf, index 4095 holding two values, then an empty
+        // hash leaf count: packed bits (2) | value count (4) | 2 * (length (4) | repr (32)) | 8.
+        let bytes = sample_tree().serialize();
+        let len = bytes.len();
+        let (bits, count, last_repr) = (len - 86, len - 84, len - 40..len - 8);
+
+        let mut non_canonical = bytes.clone();
+        non_canonical[last_repr].fill(0xff);
+        assert_eq!(
+            MerkleTree::<Fp>::deserialize(&non_canonical, 12).unwrap_err(),
+            MerkleError::NonCanonicalField
+        );
+
+        let mut padded = bytes.clone();
+        assert_eq!(padded[bits..count], [0xff, 0xf0]);
+        padded[count - 1] |= 0x01;
+        assert_eq!(
+            MerkleTree::<Fp>::deserialize(&padded, 12).unwrap_err(),
+            MerkleError::MalformedSnapshot
+        );
+
+        let mut empty = bytes[..count].to_vec();
+        empty.extend_from_slice(&0u32.to_le_bytes());
+        empty.extend_from_slice(&bytes[len - 8..]);
+        assert_eq!(
+            MerkleTree::<Fp>::deserialize(&empty, 12).unwrap_err(),
+            MerkleError::EmptyElement
+        );
+    }
+}
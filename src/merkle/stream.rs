@@ -0,0 +1,98 @@
+use super::{convert_to_bits, Element, MerkleTree};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher};
+use alloc::vec::Vec;
+use core::future::poll_fn;
+use core::pin::pin;
+use ff::{PrimeField, PrimeFieldBits};
+// Re-exported so callers need no direct `futures-core` dependency to name the bound.
+pub use futures_core::Stream;
+
+// Entries buffered before they are applied as one `add_batch`.
+const STREAM_BATCH: usize = 256;
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    // Applies `(index, element)` pairs as they arrive, in batches of up to `STREAM_BATCH`, and
+    // returns the final root. A later entry for an index wins over an earlier one. On error the
+    // batches already applied stay applied.
+    pub async fn add_stream<S: Stream<Item = (u64, Element<F>)>>(
+        &mut self,
+        stream: S,
+    ) -> Result<Digest<N>, MerkleError> {
+        let mut stream = pin!(stream);
+        let mut batch = Vec::with_capacity(STREAM_BATCH);
+        while let Some((index, element)) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            batch.push((convert_to_bits(self.height, index)?, element));
+            if batch.len() == STREAM_BATCH {
+                self.add_batch(core::mem::take(&mut batch))?;
+            }
+        }
+        self.add_batch(batch)?;
+        Ok(self.top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll, Waker};
+    use pasta_curves::Fp;
+
+    // Yields `Pending` before every item, as a network source would.
+    struct Trickle<I> {
+        items: I,
+        ready: bool,
+    }
+
+    impl<I: Iterator + Unpin> Stream for Trickle<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            self.ready = !self.ready;
+            if self.ready {
+                Poll::Ready(self.items.next())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_inserts_match_sequential_ones_with_last_write_winning() {
+        let entries: Vec<(u64, Element<Fp>)> = (0..600u64)
+            .map(|i| (i * 7 % 64, Element::new(vec![Fp::from(i + 1)]).unwrap()))
+            .collect();
+        let mut expected = MerkleTree::<Fp>::initialize(Element::default(), 6);
+        for (index, element) in &entries {
+            expected.add_element_at(*index, element).unwrap();
+        }
+
+        let mut streamed = MerkleTree::<Fp>::initialize(Element::default(), 6);
+        let stream = Trickle {
+            items: entries.into_iter(),
+            ready: false,
+        };
+        let root = block_on(streamed.add_stream(stream)).unwrap();
+        assert_eq!(root, expected.top);
+        assert_eq!(streamed.top, expected.top);
+
+        let stream = Trickle {
+            items: vec![(64, Element::default())].into_iter(),
+            ready: false,
+        };
+        assert!(block_on(streamed.add_stream(stream)).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use super::{Element, MerkleTree, Proof};
+use crate::error::MerkleError;
+use crate::hasher::{Digest, Hasher, Sha256Hasher};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// A `Proof` pinned to one field and hasher, for code that never mixes them. The untyped proof
+// stays available through `proof` and `into_inner`.
+#[derive(Clone, Debug)]
+pub struct TypedProof<
+    F: PrimeField + PrimeFieldBits,
+    H: Hasher<F, N> = Sha256Hasher,
+    const N: usize = 32,
+> {
+    proof: Proof<H, N>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> From<Proof<H, N>>
+    for TypedProof<F, H, N>
+{
+    fn from(proof: Proof<H, N>) -> Self {
+        Self {
+            proof,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> TypedProof<F, H, N> {
+    pub fn proof(&self) -> &Proof<H, N> {
+        &self.proof
+    }
+
+    pub fn into_inner(self) -> Proof<H, N> {
+        self.proof
+    }
+
+    pub fn calculate_root(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+    ) -> Result<Digest<N>, MerkleError> {
+        self.proof.calculate_root(bits_index, element)
+    }
+
+    pub fn validate(
+        &self,
+        bits_index: Vec<bool>,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool {
+        self.proof.validate(bits_index, element, root_hash)
+    }
+
+    pub fn validate_at(
+        &self,
+        index: u64,
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool {
+        self.proof.validate_at(index, element, root_hash)
+    }
+
+    pub fn validate_hash(
+        &self,
+        bits_index: &[bool],
+        leaf_hash: &[u8],
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool {
+        self.proof
+            .validate_hash::<F>(bits_index, leaf_hash, root_hash)
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    pub fn prove_typed(&self, bits_index: &[bool]) -> Result<TypedProof<F, H, N>, MerkleError> {
+        self.prove(bits_index).map(TypedProof::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::convert_to_bits;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn typed_proofs_verify_without_annotations() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 5);
+        let element = Element::new(vec![Fp::from(11)]).unwrap();
+        let bits_index = convert_to_bits(5, 11).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+
+        let proof = tree.prove_typed(&bits_index).unwrap();
+        assert!(proof.validate(bits_index.clone(), &element, tree.top));
+        assert!(proof.validate_at(11, &element, tree.top));
+        assert!(proof.validate_hash(&bits_index, &element.compute_hash(), tree.top));
+        assert!(!proof.validate_at(12, &element, tree.top));
+        assert_eq!(proof.into_inner(), tree.prove(&bits_index).unwrap());
+    }
+}
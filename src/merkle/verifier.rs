@@ -0,0 +1,152 @@
+use super::{Element, NodeMap, Proof};
+use crate::hasher::{digests_match, Digest, Hasher, Sha256Hasher};
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+
+// Verifies proofs while remembering each `hash_nodes` result, keyed by the child pair so the
+// cache holds whatever the hasher computes, prefixes included. Once `capacity` pairs are stored
+// the cache is emptied and refilled, which bounds memory at the cost of a cold restart.
+#[derive(Debug)]
+pub struct CachingVerifier<H = Sha256Hasher, const N: usize = 32> {
+    cache: RefCell<NodeMap<(Digest<N>, Digest<N>), Digest<N>>>,
+    capacity: usize,
+    _marker: PhantomData<H>,
+}
+
+impl<H, const N: usize> CachingVerifier<H, N> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: RefCell::new(NodeMap::new()),
+            capacity,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn cached(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    pub fn clear(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    pub fn verify<F: PrimeField + PrimeFieldBits>(
+        &self,
+        proof: &Proof<H, N>,
+        bits_index: &[bool],
+        element: &Element<F>,
+        root_hash: impl AsRef<[u8]>,
+    ) -> bool
+    where
+        H: Hasher<F, N>,
+    {
+        if bits_index.len() != proof.expected_height() || !proof.matches_directions(bits_index) {
+            return false;
+        }
+        let hasher = H::default();
+        let mut cache = self.cache.borrow_mut();
+        let levels = bits_index.iter().zip(&proof.sibling_hashes).rev();
+        let root = levels.fold(
+            element.compute_hash_with(&hasher),
+            |current_hash, (&direction, sibling)| {
+                let children = if direction {
+                    (*sibling, current_hash)
+                } else {
+                    (current_hash, *sibling)
+                };
+                if let Some(parent) = cache.get(&children) {
+                    return *parent;
+                }
+                let parent = hasher.hash_nodes(&children.0, &children.1);
+                if cache.len() >= self.capacity {
+                    cache.clear();
+                }
+                if self.capacity > 0 {
+                    cache.insert(children, parent);
+                }
+                parent
+            },
+        );
+        digests_match(&root, root_hash.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{convert_to_bits, MerkleTree};
+    use pasta_curves::Fp;
+    use std::cell::Cell;
+
+    std::thread_local! {
+        static NODE_HASHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[derive(Clone, Default)]
+    struct CountingHasher;
+
+    impl Hasher<Fp> for CountingHasher {
+        fn hash_leaf(&self, values: &[[u8; 32]]) -> Digest {
+            Hasher::<Fp>::hash_leaf(&Sha256Hasher, values)
+        }
+
+        fn hash_nodes(&self, left: &Digest, right: &Digest) -> Digest {
+            NODE_HASHES.with(|count| count.set(count.get() + 1));
+            Hasher::<Fp>::hash_nodes(&Sha256Hasher, left, right)
+        }
+    }
+
+    #[test]
+    fn overlapping_paths_hit_the_cache() {
+        const HEIGHT: usize = 16;
+        let mut tree = MerkleTree::<Fp, CountingHasher>::initialize(Element::default(), HEIGHT);
+        let items: Vec<_> = (0..32u64)
+            .map(|index| {
+                let element = Element::new(vec![Fp::from(index + 1)]).unwrap();
+                tree.add_element_at(index, &element).unwrap();
+                (convert_to_bits(HEIGHT, index).unwrap(), element)
+            })
+            .collect();
+        let proofs: Vec<_> = items
+            .iter()
+            .map(|(bits_index, _)| tree.prove(bits_index).unwrap())
+            .collect();
+
+        let verifier = CachingVerifier::new(1 << 10);
+        NODE_HASHES.with(|count| count.set(0));
+        for _ in 0..3 {
+            for ((bits_index, element), proof) in items.iter().zip(&proofs) {
+                assert!(verifier.verify(proof, bits_index, element, tree.top));
+                assert!(!verifier.verify(proof, bits_index, element, [0u8; 32]));
+            }
+        }
+        // Ten levels above the 32 leaves are shared by every path, and repeats cost nothing.
+        let computed = NODE_HASHES.with(Cell::get);
+        assert!(computed < 32 * HEIGHT, "{computed} node hashes");
+        assert_eq!(verifier.cached(), computed);
+
+        let (bits_index, element) = &items[0];
+        assert!(!verifier.verify(&proofs[1], bits_index, element, tree.top));
+    }
+
+    #[test]
+    fn the_cache_stays_bounded() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 8);
+        let verifier = CachingVerifier::new(20);
+        for index in 0..50u64 {
+            let element = Element::new(vec![Fp::from(index + 1)]).unwrap();
+            tree.add_element_at(index, &element).unwrap();
+            let proof = tree.prove_at(index).unwrap();
+            let bits_index = convert_to_bits(8, index).unwrap();
+            assert!(verifier.verify(&proof, &bits_index, &element, tree.top));
+            assert!(verifier.cached() <= 20);
+        }
+
+        let uncached = CachingVerifier::new(0);
+        let proof = tree.prove_at(3).unwrap();
+        let element = tree.get_element(&convert_to_bits(8, 3).unwrap()).unwrap();
+        assert!(uncached.verify(&proof, &convert_to_bits(8, 3).unwrap(), &element, tree.top));
+        assert_eq!(uncached.cached(), 0);
+    }
+}
@@ -0,0 +1,75 @@
+use super::MerkleTree;
+use crate::hasher::{Digest, Hasher};
+use alloc::string::String;
+use core::fmt::Write;
+use ff::{PrimeField, PrimeFieldBits};
+
+// Hex characters kept from each hash in a node label.
+const LABEL_WIDTH: usize = 8;
+
+impl<F: PrimeField + PrimeFieldBits, H: Hasher<F, N>, const N: usize> MerkleTree<F, H, N> {
+    // Graphviz DOT of the top `max_depth` levels, one node per position so equal empty subtrees
+    // stay separate. Empty subtrees are grey and not descended into. Node ids are `u64` heap
+    // positions, so at most 62 levels are drawn.
+    pub fn to_dot(&self, max_depth: usize) -> String {
+        let mut out = String::from("digraph merkle {\n    node [shape=box, fontname=monospace];\n");
+        self.write_dot_node(
+            &mut out,
+            &self.top,
+            0,
+            0,
+            max_depth.min(self.height).min(62),
+        );
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot_node(
+        &self,
+        out: &mut String,
+        node_hash: &Digest<N>,
+        id: u64,
+        depth: usize,
+        max_depth: usize,
+    ) {
+        let label = hex::encode(&node_hash[..LABEL_WIDTH.div_ceil(2).min(N)]);
+        let empty = self.is_default(node_hash, depth);
+        let style = if empty {
+            ", style=filled, fillcolor=lightgrey"
+        } else {
+            ""
+        };
+        writeln!(out, "    n{id} [label=\"{label}\"{style}];").unwrap();
+        if depth == max_depth || empty {
+            return;
+        }
+        let Some((left, right)) = self.children(node_hash) else {
+            return;
+        };
+        for (child_id, child) in [(2 * id + 1, left), (2 * id + 2, right)] {
+            writeln!(out, "    n{id} -> n{child_id};").unwrap();
+            self.write_dot_node(out, child, child_id, depth + 1, max_depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::Element;
+    use pasta_curves::Fp;
+
+    #[test]
+    fn dot_export_stops_at_the_requested_depth() {
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), 4);
+        tree.add_element_at(0, &Element::single(Fp::from(1)))
+            .unwrap();
+        let dot = tree.to_dot(2);
+        assert!(dot.starts_with("digraph merkle {"));
+        assert!(dot.contains(&hex::encode(&tree.top[..4])));
+        // The occupied path reaches depth 2 (ids 0, 1, 3, 4); its empty siblings are leaves.
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert_eq!(dot.matches("lightgrey").count(), 2);
+        assert_eq!(tree.to_dot(0).matches(" -> ").count(), 0);
+    }
+}
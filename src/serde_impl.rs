@@ -0,0 +1,255 @@
+use crate::merkle::{field_from_repr_bytes, Element};
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use ff::{PrimeField, PrimeFieldBits};
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+struct ReprBytes<'a>(&'a [u8]);
+
+impl Serialize for ReprBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<F: PrimeField + PrimeFieldBits> Serialize for Element<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.value.len()))?;
+        for v in &self.value {
+            seq.serialize_element(&ReprBytes(v.to_repr().as_ref()))?;
+        }
+        seq.end()
+    }
+}
+
+struct FieldValue<F>(F);
+
+struct FieldVisitor<F>(PhantomData<F>);
+
+impl<F: PrimeField> FieldVisitor<F> {
+    fn decode<E: de::Error>(bytes: &[u8]) -> Result<F, E> {
+        let width = F::Repr::default().as_ref().len();
+        if bytes.len() != width {
+            return Err(E::invalid_length(bytes.len(), &"a field element repr"));
+        }
+        field_from_repr_bytes(bytes).ok_or_else(|| E::custom("non-canonical field element"))
+    }
+}
+
+impl<'de, F: PrimeField> Visitor<'de> for FieldVisitor<F> {
+    type Value = F;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the canonical repr bytes of a field element")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<F, E> {
+        Self::decode(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<F, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(b) = seq.next_element::<u8>()? {
+            bytes.push(b);
+        }
+        Self::decode(&bytes)
+    }
+}
+
+impl<'de, F: PrimeField> Deserialize<'de> for FieldValue<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_bytes(FieldVisitor(PhantomData))
+            .map(FieldValue)
+    }
+}
+
+struct ElementVisitor<F>(PhantomData<F>);
+
+impl<'de, F: PrimeField + PrimeFieldBits> Visitor<'de> for ElementVisitor<F> {
+    type Value = Element<F>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of field element reprs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Element<F>, A::Error> {
+        let mut value = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(FieldValue(v)) = seq.next_element()? {
+            value.push(v);
+        }
+        Ok(Element { value })
+    }
+}
+
+impl<'de, F: PrimeField + PrimeFieldBits> Deserialize<'de> for Element<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(ElementVisitor(PhantomData))
+    }
+}
+
+// `serde(with = ...)` adapter for sibling lists: serde only implements its traits for arrays up
+// to a fixed length, so digests are written as byte strings and checked to be `N` bytes on the way
+// back in.
+pub(crate) mod digests {
+    use super::ReprBytes;
+    use crate::hasher::Digest;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{SerializeSeq, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        digests: &[Digest<N>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(digests.len()))?;
+        for digest in digests {
+            seq.serialize_element(&ReprBytes(digest))?;
+        }
+        seq.end()
+    }
+
+    struct DigestBytes<const N: usize>(Digest<N>);
+
+    struct DigestVisitor<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for DigestVisitor<N> {
+        type Value = Digest<N>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a {N}-byte digest")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Digest<N>, E> {
+            v.try_into().map_err(|_| E::invalid_length(v.len(), &self))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Digest<N>, A::Error> {
+            let mut bytes = Vec::with_capacity(N);
+            while let Some(b) = seq.next_element::<u8>()? {
+                bytes.push(b);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    impl<'de, const N: usize> Deserialize<'de> for DigestBytes<N> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer
+                .deserialize_bytes(DigestVisitor)
+                .map(DigestBytes)
+        }
+    }
+
+    struct DigestsVisitor<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for DigestsVisitor<N> {
+        type Value = Vec<Digest<N>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence of digests")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Digest<N>>, A::Error> {
+            let mut digests = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(DigestBytes(digest)) = seq.next_element()? {
+                digests.push(digest);
+            }
+            Ok(digests)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Vec<Digest<N>>, D::Error> {
+        deserializer.deserialize_seq(DigestsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{convert_to_bits, MerkleTree, Proof};
+    use pasta_curves::Fp;
+    use serde::de::value::{Error, SeqDeserializer};
+    use serde::de::IntoDeserializer;
+
+    fn element_from_reprs(reprs: &[Vec<u8>]) -> Result<Element<Fp>, Error> {
+        Element::deserialize(SeqDeserializer::new(reprs.iter().map(Vec::as_slice)))
+    }
+
+    fn proof_from_siblings(siblings: &[Vec<u8>]) -> Result<Proof, Error> {
+        Proof::deserialize(vec![siblings.to_vec()].into_deserializer())
+    }
+
+    fn as_vecs(digests: &[[u8; 32]]) -> Vec<Vec<u8>> {
+        digests.iter().map(|d| d.to_vec()).collect()
+    }
+
+    #[test]
+    fn element_and_proof_decode_and_verify() {
+        const HEIGHT: usize = 8;
+        let element = Element {
+            value: vec![Fp::from(11), -Fp::from(1)],
+        };
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        let bits_index = convert_to_bits(HEIGHT, 17).unwrap();
+        tree.add_element(bits_index.clone(), &element).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+
+        let reprs: Vec<Vec<u8>> = element
+            .value
+            .iter()
+            .map(|v| v.to_repr().as_ref().to_vec())
+            .collect();
+        let decoded_element = element_from_reprs(&reprs).unwrap();
+        assert_eq!(decoded_element.value, element.value);
+        let decoded_proof = proof_from_siblings(&as_vecs(&proof.sibling_hashes)).unwrap();
+        assert_eq!(decoded_proof.sibling_hashes, proof.sibling_hashes);
+        assert!(decoded_proof.validate(bits_index, &decoded_element, tree.top));
+    }
+
+    #[test]
+    fn json_round_trip_validates() {
+        const HEIGHT: usize = 8;
+        let element = Element {
+            value: vec![Fp::from(11), -Fp::from(1)],
+        };
+        let mut tree = MerkleTree::<Fp>::initialize(Element::default(), HEIGHT);
+        tree.add_element_at(17, &element).unwrap();
+        let bits_index = convert_to_bits(HEIGHT, 17).unwrap();
+        let proof = tree.prove(&bits_index).unwrap();
+
+        let proof_json = serde_json::to_string(&proof).unwrap();
+        let element_json = serde_json::to_string(&element).unwrap();
+        let decoded_proof: Proof = serde_json::from_str(&proof_json).unwrap();
+        let decoded_element: Element<Fp> = serde_json::from_str(&element_json).unwrap();
+        assert_eq!(decoded_proof, proof);
+        assert_eq!(decoded_element, element);
+        assert!(decoded_proof.validate(bits_index.clone(), &decoded_element, tree.top));
+
+        let directed = Proof::with_directions(proof.sibling_hashes.clone(), bits_index.clone());
+        let json = serde_json::to_string(&directed).unwrap();
+        let decoded: Proof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, directed);
+        assert!(decoded.validate(bits_index, &decoded_element, tree.top));
+    }
+
+    #[test]
+    fn non_canonical_field_bytes_are_rejected() {
+        assert!(element_from_reprs(&[vec![0xff; 32]]).is_err());
+        assert!(element_from_reprs(&[vec![0x01; 31]]).is_err());
+        assert!(element_from_reprs(&[Fp::from(5).to_repr().to_vec()]).is_ok());
+    }
+
+    #[test]
+    fn short_digests_are_rejected() {
+        let mut siblings = as_vecs(&[[7; 32], [8; 32]]);
+        assert!(proof_from_siblings(&siblings).is_ok());
+        siblings[1].pop();
+        assert!(proof_from_siblings(&siblings).is_err());
+    }
+}